@@ -0,0 +1,47 @@
+//! Compares re-keying Blowfish on every call with a reused `PandoraCipher`.
+
+#[macro_use]
+extern crate bencher;
+extern crate pandora_rs2;
+
+use bencher::Bencher;
+use pandora_rs2::crypt::{self, PandoraCipher};
+
+const ENCRYPT_KEY: &str = "6#26FRL$ZWD";
+const DECRYPT_KEY: &str = "R=U!LH$O2B#";
+
+/// About the size of a `station.getPlaylist` request body.
+const BODY: &str = r#"{"additionalAudioUrl":"HTTP_128_MP3","stationToken":"4220209532393187437","syncTime":1557250000,"userAuthToken":"XXoKFpSnzXbEUTGyOGlMu4L4MuBHfKgdvCRMWMOM/LSjRvuwPmZhzXsOjEEKsW0HKYm2JLsimYW2o="}"#;
+
+fn encrypt_rekeyed(b: &mut Bencher) {
+    b.bytes = BODY.len() as u64;
+    b.iter(|| crypt::encrypt(ENCRYPT_KEY, BODY));
+}
+
+fn encrypt_cached(b: &mut Bencher) {
+    let cipher = PandoraCipher::new(ENCRYPT_KEY, DECRYPT_KEY);
+    b.bytes = BODY.len() as u64;
+    b.iter(|| cipher.encrypt(BODY));
+}
+
+fn decrypt_rekeyed(b: &mut Bencher) {
+    let hex = crypt::encrypt(DECRYPT_KEY, BODY);
+    b.bytes = hex.len() as u64;
+    b.iter(|| crypt::decrypt(DECRYPT_KEY, &hex).unwrap());
+}
+
+fn decrypt_cached(b: &mut Bencher) {
+    let cipher = PandoraCipher::new(ENCRYPT_KEY, DECRYPT_KEY);
+    let hex = crypt::encrypt(DECRYPT_KEY, BODY);
+    b.bytes = hex.len() as u64;
+    b.iter(|| cipher.decrypt(&hex).unwrap());
+}
+
+benchmark_group!(
+    benches,
+    encrypt_rekeyed,
+    encrypt_cached,
+    decrypt_rekeyed,
+    decrypt_cached
+);
+benchmark_main!(benches);
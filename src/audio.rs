@@ -0,0 +1,236 @@
+//! Streaming of track audio over HTTP.
+
+use super::error::Result;
+use super::playlist::Audio;
+use super::Pandora;
+
+use reqwest::header::{CONTENT_TYPE, RANGE};
+use reqwest::{Client, Response, StatusCode};
+
+use std::io::{self, Read};
+
+/// Number of times a dropped connection is resumed before giving up.
+const MAX_RESUMES: usize = 3;
+
+/// Audio of a track opened as a `Read`.
+///
+/// If the connection drops before the whole file has been read, the stream
+/// reconnects and continues where it left off using an HTTP Range request.
+#[derive(Debug)]
+pub struct AudioStream {
+    client: Client,
+    url: String,
+    response: Response,
+    position: u64,
+    content_length: Option<u64>,
+    content_type: Option<String>,
+}
+
+impl AudioStream {
+    /// Opens the given audio using the HTTP client of `pandora`.
+    pub fn open(pandora: &Pandora, audio: &Audio) -> Result<Self> {
+        AudioStream::open_url(pandora, &audio.audio_url)
+    }
+
+    /// Opens an audio URL, e.g. a track's `additional_audio_url`, using the
+    /// HTTP client of `pandora`.
+    pub fn open_url(pandora: &Pandora, url: &str) -> Result<Self> {
+        AudioStream::with_client(pandora.client().clone(), url)
+    }
+
+    /// Opens an audio URL using the given HTTP client.
+    pub fn with_client(client: Client, url: &str) -> Result<Self> {
+        let response = client.get(url).send()?.error_for_status()?;
+        let content_length = response.content_length();
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_owned());
+
+        Ok(AudioStream {
+            client,
+            url: url.to_owned(),
+            response,
+            position: 0,
+            content_length,
+            content_type,
+        })
+    }
+
+    /// Returns the size of the audio in bytes, if the server reported it.
+    pub fn content_length(&self) -> Option<u64> {
+        self.content_length
+    }
+
+    /// Returns the MIME type of the audio, if the server reported it.
+    pub fn content_type(&self) -> Option<&str> {
+        self.content_type.as_deref()
+    }
+
+    /// Returns the number of bytes read so far.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Returns the URL being streamed.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    fn is_complete(&self) -> bool {
+        match self.content_length {
+            Some(length) => self.position >= length,
+            None => true,
+        }
+    }
+
+    /// Reconnects and skips to the current position.
+    fn resume(&mut self) -> io::Result<()> {
+        let mut response = self
+            .client
+            .get(&self.url)
+            .header(RANGE, format!("bytes={}-", self.position))
+            .send()
+            .and_then(|response| response.error_for_status())
+            .map_err(io::Error::other)?;
+
+        // Servers ignoring the range send the whole file again.
+        if response.status() != StatusCode::PARTIAL_CONTENT {
+            io::copy(&mut response.by_ref().take(self.position), &mut io::sink())?;
+        }
+
+        self.response = response;
+        Ok(())
+    }
+}
+
+impl Read for AudioStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut resumes = 0;
+        loop {
+            match self.response.read(buf) {
+                Ok(0) if !buf.is_empty() && !self.is_complete() && resumes < MAX_RESUMES => {
+                    resumes += 1;
+                    self.resume()?;
+                }
+                Ok(read) => {
+                    self.position += read as u64;
+                    return Ok(read);
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(_) if resumes < MAX_RESUMES => {
+                    resumes += 1;
+                    self.resume()?;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AudioStream;
+    use crate::testing::{credentials, header, response, serve};
+    use crate::Pandora;
+
+    use reqwest::header::{HeaderMap, HeaderValue, USER_AGENT};
+    use reqwest::Client;
+
+    use std::io::Read;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn audio() -> Vec<u8> {
+        (0..1000).map(|i| i as u8).collect()
+    }
+
+    /// Serves `audio()`, dropping the first connection after 300 bytes.
+    /// Range requests get the rest as partial content, unless
+    /// `honor_range` is false. Returns the URL and the request count.
+    fn flaky_server(honor_range: bool) -> (String, Arc<AtomicUsize>) {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let count = Arc::clone(&requests);
+        let url = serve(move |head| {
+            let audio = audio();
+            let first = count.fetch_add(1, Ordering::SeqCst) == 0;
+            let start = header(head, "Range")
+                .and_then(|range| range.strip_prefix("bytes="))
+                .and_then(|range| range.trim_end_matches('-').parse::<usize>().ok());
+            match start {
+                _ if first => response(
+                    "200 OK",
+                    &[("Content-Length", "1000"), ("Content-Type", "audio/mpeg")],
+                    &audio[..300],
+                ),
+                Some(start) if honor_range => {
+                    let length = (audio.len() - start).to_string();
+                    response(
+                        "206 Partial Content",
+                        &[("Content-Length", &length)],
+                        &audio[start..],
+                    )
+                }
+                _ => response("200 OK", &[("Content-Length", "1000")], &audio),
+            }
+        });
+        (url, requests)
+    }
+
+    fn read_all(url: &str) -> (AudioStream, Vec<u8>) {
+        let mut stream = AudioStream::with_client(Client::new(), url).unwrap();
+        let mut bytes = Vec::new();
+        stream.read_to_end(&mut bytes).unwrap();
+        (stream, bytes)
+    }
+
+    #[test]
+    fn resumes_with_a_range_request() {
+        let (url, requests) = flaky_server(true);
+        let (stream, bytes) = read_all(&url);
+        assert_eq!(bytes, audio());
+        assert_eq!(stream.position(), 1000);
+        assert_eq!(stream.content_length(), Some(1000));
+        assert_eq!(stream.content_type(), Some("audio/mpeg"));
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn resumes_from_servers_ignoring_ranges() {
+        let (url, requests) = flaky_server(false);
+        let (_, bytes) = read_all(&url);
+        assert_eq!(bytes, audio());
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn gives_up_after_a_few_resumes() {
+        let url = serve(|_| response("200 OK", &[("Content-Length", "1000")], &audio()[..10]));
+        let mut stream = AudioStream::with_client(Client::new(), &url).unwrap();
+        assert!(stream.read_to_end(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn downloads_with_the_audio_client_of_pandora() {
+        let url = serve(|head| {
+            let agent = header(head, "User-Agent").unwrap_or_default();
+            response(
+                "200 OK",
+                &[("Content-Length", &agent.len().to_string())],
+                agent.as_bytes(),
+            )
+        });
+        let mut headers = HeaderMap::new();
+        headers.insert(USER_AGENT, HeaderValue::from_static("test-player"));
+        let client = Client::builder().default_headers(headers).build().unwrap();
+        let pandora = Pandora::with_credentials(credentials()).audio_client(client);
+
+        let mut agent = String::new();
+        AudioStream::open_url(&pandora, &url)
+            .unwrap()
+            .read_to_string(&mut agent)
+            .unwrap();
+        assert_eq!(agent, "test-player");
+    }
+}
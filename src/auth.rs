@@ -1,10 +1,12 @@
-use super::crypt::decrypt;
-use super::error::Result;
+use super::crypt::{CryptError, PandoraCipher};
+use super::error::{Error, Result};
+use super::hook::Hooks;
 use super::method::Method;
-use super::request::request;
-use super::DEFAULT_ENDPOINT;
+use super::protocol::encode_call;
+use super::request::ApiRequest;
+use super::transport::Transport;
+use super::{Endpoint, DEFAULT_ENDPOINT};
 use reqwest::Client;
-use serde_json;
 
 #[derive(Debug)]
 pub struct Credentials {
@@ -12,6 +14,7 @@ pub struct Credentials {
     password: String,
     encrypt_key: String,
     decrypt_key: String,
+    cipher: PandoraCipher,
     partner_id: Option<String>,
     partner_auth_token: Option<String>,
     sync_time: Option<u64>,
@@ -21,44 +24,70 @@ pub struct Credentials {
 
 impl Credentials {
     pub fn new(username: &str, password: &str) -> Result<Self> {
-        let client = Client::new();
+        Credentials::login(&Client::new(), DEFAULT_ENDPOINT, username, password)
+    }
+
+    /// Logs in through `endpoint`.
+    pub fn login<X>(
+        transport: &X,
+        endpoint: Endpoint<'static>,
+        username: &str,
+        password: &str,
+    ) -> Result<Self>
+    where
+        X: Transport + ?Sized,
+    {
+        Credentials::login_with_hooks(transport, &Hooks::default(), endpoint, username, password)
+    }
+
+    /// Like `login`, running `hooks` around both calls.
+    pub(crate) fn login_with_hooks<X>(
+        transport: &X,
+        hooks: &Hooks,
+        endpoint: Endpoint<'static>,
+        username: &str,
+        password: &str,
+    ) -> Result<Self>
+    where
+        X: Transport + ?Sized,
+    {
         let partner = Partner::default();
+
+        let request = encode_call(endpoint, &partner, None)?;
+        let partner_login = hooks.send(transport, endpoint, &request)?;
+        let mut credentials =
+            Credentials::with_partner_login(username, password, &partner, partner_login)?;
+
+        let user_login = UserLoginRequest::new(username.to_owned(), password.to_owned());
+        let request = encode_call(endpoint, &user_login, Some(&credentials))?;
+        credentials.set_user_login(hooks.send(transport, endpoint, &request)?);
+
+        Ok(credentials)
+    }
+
+    /// Creates the credentials of a user who isn't logged in yet from the
+    /// result of `auth.partnerLogin`. Logging in then takes sending a
+    /// `UserLoginRequest` with them and passing the result to
+    /// `set_user_login`.
+    pub fn with_partner_login(
+        username: &str,
+        password: &str,
+        partner: &Partner,
+        partner_login: PartnerLogin,
+    ) -> Result<Self> {
         let mut credentials = Credentials {
             username: username.to_owned(),
             password: password.to_owned(),
             encrypt_key: partner.encrypt_password.clone(),
             decrypt_key: partner.decrypt_password.clone(),
+            cipher: partner.cipher(),
             partner_id: None,
             partner_auth_token: None,
             sync_time: None,
             user_id: None,
             user_auth_token: None,
         };
-
-        let partner_login: PartnerLogin = request(
-            &client,
-            DEFAULT_ENDPOINT,
-            Method::AuthPartnerLogin,
-            Some(serde_json::to_value(&partner)?),
-            None,
-        )?;
-        credentials.set_partner_login(partner_login);
-
-        let user_login_body = serde_json::to_value(&UserLoginRequest::new(
-            username.to_owned(),
-            password.to_owned(),
-        ))
-        .expect("Fatal error creating user login body");
-        let user_login: UserLogin = request(
-            &client,
-            DEFAULT_ENDPOINT,
-            Method::AuthUserLogin,
-            Some(user_login_body),
-            Some(&credentials),
-        )
-        .expect("Fatal error requesting user creds");
-        credentials.set_user_login(user_login);
-
+        credentials.set_partner_login(partner_login)?;
         Ok(credentials)
     }
 
@@ -88,65 +117,77 @@ impl Credentials {
         &self.decrypt_key
     }
 
-    pub fn partner_id<'a>(&'a self) -> Option<&'a str> {
+    /// Returns the cipher for the partner's keys.
+    pub fn cipher(&self) -> &PandoraCipher {
+        &self.cipher
+    }
+
+    pub fn partner_id(&self) -> Option<&str> {
         match self.partner_id {
             Some(ref partner_id) => Some(partner_id.as_str()),
             None => None,
         }
     }
 
-    pub fn partner_auth_token<'a>(&'a self) -> Option<&'a str> {
+    pub fn partner_auth_token(&self) -> Option<&str> {
         match self.partner_auth_token {
             Some(ref partner_auth_token) => Some(partner_auth_token.as_str()),
             None => None,
         }
     }
 
-    pub fn sync_time<'a>(&'a self) -> Option<&'a u64> {
-        match self.sync_time {
-            Some(ref sync_time) => Some(&sync_time),
-            None => None,
-        }
+    pub fn sync_time(&self) -> Option<&u64> {
+        self.sync_time.as_ref()
     }
 
-    pub fn user_id<'a>(&'a self) -> Option<&'a str> {
+    pub fn user_id(&self) -> Option<&str> {
         match self.user_id {
             Some(ref user_id) => Some(user_id.as_str()),
             None => None,
         }
     }
 
-    pub fn user_auth_token<'a>(&'a self) -> Option<&'a str> {
+    pub fn user_auth_token(&self) -> Option<&str> {
         match self.user_auth_token {
             Some(ref user_auth_token) => Some(user_auth_token.as_str()),
             None => None,
         }
     }
 
-    fn set_partner_login(&mut self, partner_login: PartnerLogin) {
-        use std::str;
-
-        let sync_time_bytes: Vec<u8> = decrypt(self.decrypt_key(), &partner_login.sync_time)
-            .iter()
-            .skip(4)
-            .cloned()
-            .collect();
-        let sync_time_str = str::from_utf8(&sync_time_bytes).unwrap_or("0");
-
-        let sync_time = sync_time_str.parse::<u64>().unwrap_or(0);
+    fn set_partner_login(&mut self, partner_login: PartnerLogin) -> Result<()> {
+        let sync_time = decode_sync_time(&self.cipher, &partner_login.sync_time)?;
 
         self.partner_id = Some(partner_login.partner_id.clone());
         self.partner_auth_token = Some(partner_login.partner_auth_token.clone());
         self.sync_time = Some(sync_time);
+        Ok(())
     }
 
-    fn set_user_login(&mut self, user_login: UserLogin) {
+    /// Stores the result of `auth.userLogin`.
+    pub fn set_user_login(&mut self, user_login: UserLogin) {
         self.user_id = user_login.user_id.clone();
         self.user_auth_token = Some(user_login.user_auth_token.clone());
     }
 }
 
-#[derive(Serialize)]
+/// Decodes the encrypted `syncTime` returned by `auth.partnerLogin`
+/// using the partner's decryption key.
+pub fn decode_sync_time(cipher: &PandoraCipher, sync_time: &str) -> Result<u64> {
+    use std::str;
+
+    // The first four bytes of the decrypted sync time are garbage.
+    let sync_time_bytes = cipher.decrypt(sync_time)?;
+    let sync_time_bytes = sync_time_bytes.get(4..).unwrap_or(&[]);
+    let sync_time_str = str::from_utf8(sync_time_bytes).map_err(CryptError::InvalidUtf8)?;
+
+    sync_time_str
+        .parse::<u64>()
+        .map_err(|_| Error::InvalidSyncTime(sync_time_str.to_owned()))
+}
+
+/// Device profile used for `auth.partnerLogin`, with the keys used to
+/// encrypt requests and decrypt responses.
+#[derive(Debug, Clone, Serialize)]
 pub struct Partner {
     username: String,
     password: String,
@@ -161,14 +202,7 @@ pub struct Partner {
 
 impl Default for Partner {
     fn default() -> Self {
-        Partner {
-            username: "android".to_owned(),
-            password: "AC7IBG09A3DTSYM4R41UJWL07VLN8JI7".to_owned(),
-            device_model: "android-generic".to_owned(),
-            version: "5".to_owned(),
-            encrypt_password: "6#26FRL$ZWD".to_owned(),
-            decrypt_password: "R=U!LH$O2B#".to_owned(),
-        }
+        Partner::android()
     }
 }
 
@@ -182,14 +216,112 @@ impl Partner {
         decrypt_password: String,
     ) -> Self {
         Partner {
-            username: username,
-            password: password,
-            device_model: device_model,
-            version: version,
-            encrypt_password: encrypt_password,
-            decrypt_password: decrypt_password,
+            username,
+            password,
+            device_model,
+            version,
+            encrypt_password,
+            decrypt_password,
+        }
+    }
+
+    pub fn android() -> Self {
+        Partner::from_strs(
+            "android",
+            "AC7IBG09A3DTSYM4R41UJWL07VLN8JI7",
+            "android-generic",
+            "6#26FRL$ZWD",
+            "R=U!LH$O2B#",
+        )
+    }
+
+    pub fn ios() -> Self {
+        Partner::from_strs(
+            "iphone",
+            "P2E4FC0EAD3*878N92B2CDp34I0B1@388137C",
+            "IP01",
+            "721^26xE22776",
+            "20zE1E47BE57$51",
+        )
+    }
+
+    pub fn palm() -> Self {
+        Partner::from_strs(
+            "palm",
+            "IUC7IBG09A3JTSYM4N11UJWL07VLH8JP0",
+            "pre",
+            "%526CBL$ZU3",
+            "E#U$MY$O2B=",
+        )
+    }
+
+    pub fn windows_mobile() -> Self {
+        Partner::from_strs(
+            "winmo",
+            "ED227E10a628EB0E8Pm825Dw7114AC39",
+            "VERIZON_MOTOQ9C",
+            "v93C8C2s12E0EBD",
+            "7D671jt0C5E5d251",
+        )
+    }
+
+    /// Returns one of the known partner profiles by the partner's
+    /// username, e.g. `android` or `iphone`.
+    pub fn by_name(name: &str) -> Option<Self> {
+        match name {
+            "android" => Some(Partner::android()),
+            "iphone" | "ios" => Some(Partner::ios()),
+            "palm" => Some(Partner::palm()),
+            "winmo" => Some(Partner::windows_mobile()),
+            _ => None,
         }
     }
+
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+
+    pub fn device_model(&self) -> &str {
+        &self.device_model
+    }
+
+    pub fn encrypt_password(&self) -> &str {
+        &self.encrypt_password
+    }
+
+    pub fn decrypt_password(&self) -> &str {
+        &self.decrypt_password
+    }
+
+    /// Returns a cipher for the partner's keys.
+    pub fn cipher(&self) -> PandoraCipher {
+        PandoraCipher::new(&self.encrypt_password, &self.decrypt_password)
+    }
+
+    fn from_strs(
+        username: &str,
+        password: &str,
+        device_model: &str,
+        encrypt_password: &str,
+        decrypt_password: &str,
+    ) -> Self {
+        Partner::new(
+            username.to_owned(),
+            password.to_owned(),
+            device_model.to_owned(),
+            "5".to_owned(),
+            encrypt_password.to_owned(),
+            decrypt_password.to_owned(),
+        )
+    }
+}
+
+impl ApiRequest for Partner {
+    type Response = PartnerLogin;
+
+    fn method(&self) -> Method {
+        Method::AuthPartnerLogin
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -216,8 +348,9 @@ pub struct UserLogin {
     pub user_auth_token: String,
 }
 
+/// Body of `auth.userLogin`.
 #[derive(Serialize)]
-struct UserLoginRequest {
+pub struct UserLoginRequest {
     #[serde(rename = "loginType")]
     login_type: String,
     username: String,
@@ -228,8 +361,28 @@ impl UserLoginRequest {
     pub fn new(username: String, password: String) -> Self {
         UserLoginRequest {
             login_type: "user".to_owned(),
-            username: username,
-            password: password,
+            username,
+            password,
         }
     }
 }
+
+impl ApiRequest for UserLoginRequest {
+    type Response = UserLogin;
+
+    fn method(&self) -> Method {
+        Method::AuthUserLogin
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_sync_time, Partner};
+
+    #[test]
+    fn decodes_sync_time() {
+        let cipher = Partner::android().cipher();
+        let sync_time = "87f9b461070d0efd5a143d430aafebf9";
+        assert_eq!(decode_sync_time(&cipher, sync_time).unwrap(), 1477631903);
+    }
+}
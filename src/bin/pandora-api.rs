@@ -0,0 +1,551 @@
+//! REST/JSON control API over a single Pandora session.
+//!
+//! Logs in once with the `PANDORA_USERNAME` and `PANDORA_PASSWORD`
+//! environment variables and serves the routes below on the loopback
+//! interface only, so local services can share the session. Bodies are
+//! JSON with the field names Pandora uses, e.g. `{"musicToken": "..."}`.
+//!
+//! ```text
+//! GET    /stations                       list stations
+//! POST   /stations                       create {musicToken}
+//! GET    /stations/{id}                  get a station
+//! PATCH  /stations/{id}                  rename {stationName}
+//! DELETE /stations/{id}                  delete a station
+//! POST   /stations/{id}/seeds            add a seed {musicToken}
+//! DELETE /seeds/{id}                     remove a seed
+//! GET    /stations/{id}/playlist         next tracks of a station
+//! POST   /stations/{id}/feedback         rate {trackToken, isPositive}
+//! GET    /search?q=TEXT                  search songs and artists
+//! GET    /bookmarks                      list bookmarks
+//! POST   /bookmarks/songs                bookmark a song {trackToken}
+//! POST   /bookmarks/artists              bookmark an artist {trackToken}
+//! DELETE /bookmarks/songs/{token}        delete a song bookmark
+//! DELETE /bookmarks/artists/{token}      delete an artist bookmark
+//! ```
+//!
+//! Failures are answered with `{"error": {"code", "number", "message"}}`,
+//! where `code` is the `ApiErrorCode` name for Pandora API errors.
+//!
+//! Path segments are percent-decoded, so tokens may contain `/` as `%2F`.
+//! To keep web pages from driving the API, requests must name
+//! `127.0.0.1:PORT`, `localhost:PORT` or `[::1]:PORT` as their `Host`,
+//! and requests other than `GET` and `HEAD` must have a `Content-Type` of
+//! `application/json`.
+
+extern crate pandora_rs2;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate tiny_http;
+extern crate url;
+
+use pandora_rs2::error::Error;
+use pandora_rs2::music::Music;
+use pandora_rs2::stations::Seed;
+use pandora_rs2::Pandora;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response, Server};
+use url::form_urlencoded;
+use url::percent_encoding::percent_decode;
+
+use std::env;
+use std::error::Error as StdError;
+use std::io;
+use std::net::Ipv4Addr;
+use std::process;
+use std::sync::Arc;
+use std::thread;
+
+const USAGE: &str = "\
+Usage: pandora-api [--port PORT]
+
+Options:
+    --port PORT     Port to listen on at 127.0.0.1 (default: 8080)
+";
+
+const DEFAULT_PORT: u16 = 8080;
+
+fn main() {
+    let port = match parse_args(env::args().skip(1)) {
+        Ok(port) => port,
+        Err(message) => {
+            eprint!("pandora-api: {}\n\n{}", message, USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Err(err) = run(port) {
+        eprintln!("pandora-api: {}", err);
+        process::exit(1);
+    }
+}
+
+fn parse_args<I>(mut args: I) -> Result<u16, String>
+where
+    I: Iterator<Item = String>,
+{
+    let mut port = DEFAULT_PORT;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--port" => {
+                let value = args.next().ok_or("--port needs a value")?;
+                port = value
+                    .parse()
+                    .map_err(|_| format!("invalid port {:?}", value))?;
+            }
+            "-h" | "--help" => {
+                print!("{}", USAGE);
+                process::exit(0);
+            }
+            _ => return Err(format!("unexpected argument {:?}", arg)),
+        }
+    }
+    Ok(port)
+}
+
+fn run(port: u16) -> Result<(), Box<dyn StdError + Send + Sync>> {
+    let username = env::var("PANDORA_USERNAME").map_err(|_| "PANDORA_USERNAME is not set")?;
+    let password = env::var("PANDORA_PASSWORD").map_err(|_| "PANDORA_PASSWORD is not set")?;
+    let pandora = Arc::new(Pandora::new(&username, &password)?);
+
+    let server = Server::http((Ipv4Addr::LOCALHOST, port))?;
+    eprintln!("pandora-api: listening on http://127.0.0.1:{}/", port);
+
+    for request in server.incoming_requests() {
+        let pandora = Arc::clone(&pandora);
+        thread::spawn(move || {
+            if let Err(err) = handle(&pandora, port, request) {
+                eprintln!("pandora-api: {}", err);
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Error answered to a client.
+#[derive(Serialize)]
+struct ApiError {
+    #[serde(skip)]
+    status: u16,
+    /// `ApiErrorCode` name for API errors, otherwise the kind of failure.
+    code: &'static str,
+    /// Numeric Pandora error code, for API errors.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    number: Option<u32>,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: u16, code: &'static str, message: String) -> Self {
+        ApiError {
+            status,
+            code,
+            number: None,
+            message,
+        }
+    }
+
+    fn not_found() -> Self {
+        ApiError::new(404, "NotFound", "no such route".to_owned())
+    }
+}
+
+impl From<Error> for ApiError {
+    fn from(err: Error) -> Self {
+        let code = match err {
+            Error::Api { ref code, .. } => {
+                return ApiError {
+                    status: 502,
+                    code: code.name(),
+                    number: code.code(),
+                    message: err.to_string(),
+                }
+            }
+            Error::Io(_) => "Io",
+            Error::Codec(_) => "Codec",
+            Error::Http(_) => "Http",
+            Error::Crypt(_) => "Crypt",
+            Error::InvalidSyncTime(_) => "InvalidSyncTime",
+            Error::MissingResult { .. } => "MissingResult",
+            Error::Decode { .. } => "Decode",
+            Error::SkipLimitReached { .. } => "SkipLimitReached",
+        };
+        ApiError::new(502, code, err.to_string())
+    }
+}
+
+type ApiResult = Result<Value, ApiError>;
+
+#[derive(Deserialize)]
+struct MusicTokenBody {
+    #[serde(rename = "musicToken")]
+    music_token: String,
+}
+
+#[derive(Deserialize)]
+struct RenameBody {
+    #[serde(rename = "stationName")]
+    station_name: String,
+}
+
+#[derive(Deserialize)]
+struct TrackTokenBody {
+    #[serde(rename = "trackToken")]
+    track_token: String,
+}
+
+#[derive(Deserialize)]
+struct FeedbackBody {
+    #[serde(rename = "trackToken")]
+    track_token: String,
+    #[serde(rename = "isPositive")]
+    is_positive: bool,
+}
+
+fn handle(pandora: &Pandora, port: u16, mut request: Request) -> io::Result<()> {
+    let mut body = String::new();
+    let result = match request.as_reader().read_to_string(&mut body) {
+        Ok(_) => respond(
+            pandora,
+            port,
+            request.method(),
+            request.url(),
+            header(&request, "Host"),
+            header(&request, "Content-Type"),
+            &body,
+        ),
+        Err(err) => Err(ApiError::new(400, "Io", err.to_string())),
+    };
+
+    let (status, value) = match result {
+        Ok(value) => (200, value),
+        Err(err) => (err.status, json!({ "error": err })),
+    };
+    let content_type = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap();
+    let response = Response::from_string(value.to_string())
+        .with_status_code(status)
+        .with_header(content_type);
+    request.respond(response)
+}
+
+fn header<'a>(request: &'a Request, name: &'static str) -> Option<&'a str> {
+    request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv(name))
+        .map(|header| header.value.as_str())
+}
+
+/// Names a client may use for the loopback interface in `Host`.
+const LOCAL_HOSTS: [&str; 3] = ["127.0.0.1", "localhost", "[::1]"];
+
+/// Returns true if `host` names the loopback interface and our port.
+fn is_local_host(host: &str, port: u16) -> bool {
+    LOCAL_HOSTS
+        .iter()
+        .any(|name| host.eq_ignore_ascii_case(&format!("{}:{}", name, port)))
+}
+
+/// Checks the headers of a request and routes it.
+fn respond(
+    pandora: &Pandora,
+    port: u16,
+    method: &Method,
+    url: &str,
+    host: Option<&str>,
+    content_type: Option<&str>,
+    body: &str,
+) -> ApiResult {
+    // A page on a domain resolving to 127.0.0.1 could otherwise reach us.
+    if !host.is_some_and(|host| is_local_host(host, port)) {
+        return Err(ApiError::new(
+            403,
+            "InvalidHost",
+            format!("unexpected host {:?}", host),
+        ));
+    }
+    // Pages can send forms cross-origin, but not JSON without asking first.
+    let is_json = content_type
+        .and_then(|value| value.split(';').next())
+        .is_some_and(|mime| mime.trim().eq_ignore_ascii_case("application/json"));
+    if *method != Method::Get && *method != Method::Head && !is_json {
+        return Err(ApiError::new(
+            415,
+            "UnsupportedMediaType",
+            "expected Content-Type: application/json".to_owned(),
+        ));
+    }
+
+    let (path, query) = match url.find('?') {
+        Some(index) => (&url[..index], &url[index + 1..]),
+        None => (url, ""),
+    };
+    let segments = path
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .map(|s| percent_decode(s.as_bytes()).decode_utf8())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| ApiError::new(400, "InvalidPath", e.to_string()))?;
+    let segments: Vec<&str> = segments.iter().map(|s| s.as_ref()).collect();
+    route(pandora, method, &segments, query, body)
+}
+
+fn route(
+    pandora: &Pandora,
+    method: &Method,
+    segments: &[&str],
+    query: &str,
+    body: &str,
+) -> ApiResult {
+    let stations = pandora.stations();
+    let bookmarks = pandora.bookmarks();
+
+    match (method, segments) {
+        (Method::Get, ["stations"]) => result(stations.list()?),
+        (Method::Post, ["stations"]) => {
+            let body: MusicTokenBody = parse_body(body)?;
+            result(stations.create(&body.music_token)?)
+        }
+        (Method::Get, ["stations", id]) => result(stations.station(&id.to_string())?),
+        (Method::Patch, ["stations", id]) => {
+            let body: RenameBody = parse_body(body)?;
+            result(stations.rename(&id.to_string(), &body.station_name)?)
+        }
+        (Method::Delete, ["stations", id]) => {
+            stations.delete(&id.to_string())?;
+            Ok(json!({ "ok": true }))
+        }
+        (Method::Post, ["stations", id, "seeds"]) => {
+            let body: MusicTokenBody = parse_body(body)?;
+            result(stations.add_seed(&id.to_string(), &body.music_token)?)
+        }
+        (Method::Delete, ["seeds", id]) => {
+            stations.remove_seed(&Seed {
+                seed_id: id.to_string(),
+            })?;
+            Ok(json!({ "ok": true }))
+        }
+        (Method::Get, ["stations", id, "playlist"]) => {
+            result(stations.playlist(&id.to_string()).list()?)
+        }
+        (Method::Post, ["stations", id, "feedback"]) => {
+            let body: FeedbackBody = parse_body(body)?;
+            stations
+                .playlist(&id.to_string())
+                .rate(body.track_token, body.is_positive)?;
+            Ok(json!({ "ok": true }))
+        }
+
+        (Method::Get, ["search"]) => {
+            let text = form_urlencoded::parse(query.as_bytes())
+                .find(|(key, _)| key == "q")
+                .map(|(_, value)| value.into_owned())
+                .filter(|text| !text.is_empty())
+                .ok_or_else(|| ApiError::new(400, "InvalidParams", "missing q".to_owned()))?;
+            result(Music::new(pandora).search(&text)?)
+        }
+
+        (Method::Get, ["bookmarks"]) => result(bookmarks.list()?),
+        (Method::Post, ["bookmarks", "songs"]) => {
+            let body: TrackTokenBody = parse_body(body)?;
+            result(bookmarks.add_song(body.track_token)?)
+        }
+        (Method::Post, ["bookmarks", "artists"]) => {
+            let body: TrackTokenBody = parse_body(body)?;
+            result(bookmarks.add_artist(body.track_token)?)
+        }
+        (Method::Delete, ["bookmarks", "songs", token]) => {
+            bookmarks.delete_song(token)?;
+            Ok(json!({ "ok": true }))
+        }
+        (Method::Delete, ["bookmarks", "artists", token]) => {
+            bookmarks.delete_artist(token)?;
+            Ok(json!({ "ok": true }))
+        }
+
+        _ => Err(ApiError::not_found()),
+    }
+}
+
+fn parse_body<T>(body: &str) -> Result<T, ApiError>
+where
+    T: DeserializeOwned,
+{
+    serde_json::from_str(body).map_err(|e| ApiError::new(400, "InvalidParams", e.to_string()))
+}
+
+fn result<T>(value: T) -> ApiResult
+where
+    T: Serialize,
+{
+    serde_json::to_value(value).map_err(|e| ApiError::new(500, "Codec", e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{respond, ApiResult, DEFAULT_PORT};
+
+    use pandora_rs2::auth::{Partner, PartnerLogin, UserLogin};
+    use pandora_rs2::error::Result;
+    use pandora_rs2::protocol::{HttpRequest, HttpResponse};
+    use pandora_rs2::{Credentials, Pandora, Transport};
+
+    use serde_json::{json, Value};
+    use tiny_http::Method;
+
+    use std::sync::{Arc, Mutex};
+
+    const HOST: &str = "127.0.0.1:8080";
+    const JSON: &str = "application/json; charset=utf-8";
+
+    /// Transport keeping the method and body of every call, answering
+    /// each with an empty success.
+    #[derive(Debug, Default)]
+    struct Calls(Mutex<Vec<(String, Value)>>);
+
+    impl Transport for Calls {
+        fn execute(&self, request: &HttpRequest) -> Result<HttpResponse> {
+            let body = serde_json::from_str(&request.plain_body)?;
+            let mut calls = self.0.lock().unwrap();
+            calls.push((request.method.to_string(), body));
+            Ok(HttpResponse {
+                status: 200,
+                body: r#"{"stat":"ok","result":{}}"#.to_owned(),
+            })
+        }
+    }
+
+    fn pandora(calls: &Arc<Calls>) -> Pandora {
+        let partner_login = PartnerLogin {
+            partner_id: "42".to_owned(),
+            partner_auth_token: "partner".to_owned(),
+            sync_time: "87f9b461070d0efd5a143d430aafebf9".to_owned(),
+        };
+        let mut credentials =
+            Credentials::with_partner_login("me", "hunter2", &Partner::android(), partner_login)
+                .unwrap();
+        credentials.set_user_login(UserLogin {
+            user_id: Some("1".to_owned()),
+            user_auth_token: "user".to_owned(),
+        });
+        Pandora::with_credentials(credentials).transport(Arc::clone(calls))
+    }
+
+    fn send(
+        calls: &Arc<Calls>,
+        method: Method,
+        url: &str,
+        host: &str,
+        content_type: Option<&str>,
+    ) -> ApiResult {
+        let pandora = pandora(calls);
+        respond(
+            &pandora,
+            DEFAULT_PORT,
+            &method,
+            url,
+            Some(host),
+            content_type,
+            "",
+        )
+    }
+
+    fn status(result: ApiResult) -> u16 {
+        result.err().map_or(200, |err| err.status)
+    }
+
+    #[test]
+    fn decodes_path_segments() {
+        let calls = Arc::new(Calls::default());
+        let result = send(
+            &calls,
+            Method::Delete,
+            "/stations/a%2Fb%20c",
+            HOST,
+            Some(JSON),
+        );
+        assert_eq!(result.ok(), Some(json!({ "ok": true })));
+
+        let calls = calls.0.lock().unwrap();
+        assert_eq!(calls[0].0, "station.deleteStation");
+        assert_eq!(calls[0].1["stationToken"], "a/b c");
+    }
+
+    #[test]
+    fn rejects_invalid_paths_and_unknown_routes() {
+        let calls = Arc::new(Calls::default());
+        let get = |url| status(send(&calls, Method::Get, url, HOST, None));
+        assert_eq!(get("/stations/%FF"), 400);
+        assert_eq!(get("/nowhere"), 404);
+        assert_eq!(get("/search?q="), 400);
+        assert!(calls.0.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_other_hosts() {
+        let calls = Arc::new(Calls::default());
+        for host in &[
+            "evil.example:8080",
+            "127.0.0.1:8081",
+            "localhost:8081",
+            "[::1]:8081",
+            "127.0.0.1",
+            "localhost.evil.example:8080",
+        ] {
+            assert_eq!(
+                status(send(&calls, Method::Get, "/stations", host, None)),
+                403
+            );
+        }
+        let pandora = pandora(&calls);
+        let result = respond(
+            &pandora,
+            DEFAULT_PORT,
+            &Method::Get,
+            "/stations",
+            None,
+            None,
+            "",
+        );
+        assert_eq!(status(result), 403);
+        assert!(calls.0.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn accepts_loopback_hosts() {
+        let calls = Arc::new(Calls::default());
+        for host in &[HOST, "localhost:8080", "LocalHost:8080", "[::1]:8080"] {
+            let result = send(&calls, Method::Delete, "/seeds/1", host, Some(JSON));
+            assert_eq!(status(result), 200, "{}", host);
+        }
+        assert_eq!(calls.0.lock().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn rejects_mutations_without_json() {
+        let calls = Arc::new(Calls::default());
+        for content_type in &[
+            None,
+            Some("text/plain"),
+            Some("application/x-www-form-urlencoded"),
+        ] {
+            let result = send(&calls, Method::Delete, "/seeds/1", HOST, *content_type);
+            assert_eq!(status(result), 415);
+        }
+        assert!(calls.0.lock().unwrap().is_empty());
+
+        let result = send(
+            &calls,
+            Method::Delete,
+            "/seeds/1",
+            HOST,
+            Some("Application/JSON"),
+        );
+        assert_eq!(status(result), 200);
+        assert_eq!(calls.0.lock().unwrap()[0].0, "station.deleteMusic");
+    }
+}
@@ -0,0 +1,199 @@
+//! Encrypts and decrypts Pandora API bodies for protocol debugging.
+//!
+//! Request bodies are encrypted with the partner's encryption key, while
+//! the `syncTime` of `auth.partnerLogin` is encrypted with its decryption
+//! key, so `encrypt` and `decrypt` default to the former and `sync-time`
+//! to the latter.
+
+extern crate pandora_rs2;
+extern crate serde_json;
+
+use pandora_rs2::auth::{decode_sync_time, Partner};
+use pandora_rs2::crypt::PandoraCipher;
+
+use serde_json::Value;
+
+use std::env;
+use std::fs::File;
+use std::io::{self, Read};
+use std::process;
+
+const USAGE: &str = "\
+Usage: pandora-crypt [--partner NAME] [--key KEY] COMMAND [FILE]
+
+Commands:
+    encrypt     Encrypts a request body
+    decrypt     Decrypts a request body and pretty-prints its JSON
+    sync-time   Decodes the syncTime of an auth.partnerLogin response
+
+Reads from FILE, or from stdin if no file is given.
+
+Options:
+    --partner NAME  Partner profile: android (default), iphone, palm, winmo
+    --key KEY       Overrides the key taken from the partner profile
+";
+
+enum Command {
+    Encrypt,
+    Decrypt,
+    SyncTime,
+}
+
+struct Args {
+    command: Command,
+    partner: Partner,
+    key: Option<String>,
+    file: Option<String>,
+}
+
+fn main() {
+    let args = match parse_args(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(message) => {
+            eprint!("pandora-crypt: {}\n\n{}", message, USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Err(message) = run(args) {
+        eprintln!("pandora-crypt: {}", message);
+        process::exit(1);
+    }
+}
+
+fn parse_args<I>(mut args: I) -> Result<Args, String>
+where
+    I: Iterator<Item = String>,
+{
+    let mut command = None;
+    let mut partner = Partner::default();
+    let mut key = None;
+    let mut file = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--partner" => {
+                let name = args.next().ok_or("--partner needs a value")?;
+                partner =
+                    Partner::by_name(&name).ok_or_else(|| format!("unknown partner {:?}", name))?;
+            }
+            "--key" => key = Some(args.next().ok_or("--key needs a value")?),
+            "-h" | "--help" => {
+                print!("{}", USAGE);
+                process::exit(0);
+            }
+            "encrypt" if command.is_none() => command = Some(Command::Encrypt),
+            "decrypt" if command.is_none() => command = Some(Command::Decrypt),
+            "sync-time" if command.is_none() => command = Some(Command::SyncTime),
+            _ if command.is_some() && file.is_none() => file = Some(arg),
+            _ => return Err(format!("unexpected argument {:?}", arg)),
+        }
+    }
+
+    Ok(Args {
+        command: command.ok_or("missing command")?,
+        partner,
+        key,
+        file,
+    })
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let input = read_input(args.file.as_deref()).map_err(|e| e.to_string())?;
+    println!("{}", convert(&args, input.trim())?);
+    Ok(())
+}
+
+/// Runs the command of `args` on `input` and returns what to print.
+fn convert(args: &Args, input: &str) -> Result<String, String> {
+    match args.command {
+        Command::Encrypt => {
+            let key = args
+                .key
+                .as_deref()
+                .unwrap_or(args.partner.encrypt_password());
+            let cipher = PandoraCipher::new(key, key);
+            Ok(cipher.encrypt(input))
+        }
+        Command::Decrypt => {
+            let key = args
+                .key
+                .as_deref()
+                .unwrap_or(args.partner.encrypt_password());
+            let cipher = PandoraCipher::new(key, key);
+            let plain = cipher.decrypt(input).map_err(|e| e.to_string())?;
+            let plain = String::from_utf8_lossy(&plain);
+            match serde_json::from_str::<Value>(&plain) {
+                Ok(json) => Ok(serde_json::to_string_pretty(&json).unwrap()),
+                Err(_) => Ok(plain.into_owned()),
+            }
+        }
+        Command::SyncTime => {
+            let key = args
+                .key
+                .as_deref()
+                .unwrap_or(args.partner.decrypt_password());
+            let cipher = PandoraCipher::new(key, key);
+            let sync_time = find_sync_time(input);
+            let sync_time = decode_sync_time(&cipher, &sync_time).map_err(|e| e.to_string())?;
+            Ok(sync_time.to_string())
+        }
+    }
+}
+
+/// Returns the encrypted sync time from a full `auth.partnerLogin`
+/// response, its `result` object, or the bare hex string.
+fn find_sync_time(input: &str) -> String {
+    let json = match serde_json::from_str::<Value>(input) {
+        Ok(json) => json,
+        Err(_) => return input.to_owned(),
+    };
+    let sync_time = json
+        .pointer("/result/syncTime")
+        .or_else(|| json.get("syncTime"))
+        .unwrap_or(&json);
+    match *sync_time {
+        Value::String(ref sync_time) => sync_time.clone(),
+        _ => input.to_owned(),
+    }
+}
+
+fn read_input(file: Option<&str>) -> io::Result<String> {
+    let mut input = String::new();
+    match file {
+        Some(path) => File::open(path)?.read_to_string(&mut input)?,
+        None => io::stdin().read_to_string(&mut input)?,
+    };
+    Ok(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{convert, parse_args, Args};
+
+    fn args(args: &str) -> Args {
+        parse_args(args.split_whitespace().map(str::to_owned)).unwrap()
+    }
+
+    #[test]
+    fn decrypts_what_it_encrypts() {
+        let body = r#"{"username":"me","password":"hunter2"}"#;
+        for partner in &["android", "iphone"] {
+            let encrypted =
+                convert(&args(&format!("--partner {} encrypt", partner)), body).unwrap();
+            assert_ne!(encrypted, body);
+
+            let decrypt = args(&format!("--partner {} decrypt", partner));
+            let decrypted = convert(&decrypt, &encrypted).unwrap();
+            let json: serde_json::Value = serde_json::from_str(&decrypted).unwrap();
+            assert_eq!(
+                json,
+                serde_json::from_str::<serde_json::Value>(body).unwrap()
+            );
+        }
+
+        let encrypted = convert(&args("--key secret encrypt"), "not json").unwrap();
+        let decrypted = convert(&args("--key secret decrypt"), &encrypted).unwrap();
+        assert_eq!(decrypted, "not json");
+    }
+}
@@ -0,0 +1,162 @@
+//! Shoutcast/ICY metadata interleaved into an audio stream.
+//!
+//! Clients that send `Icy-MetaData: 1` are told the metadata interval with
+//! the `icy-metaint` header. After every `metaint` bytes of audio comes a
+//! length byte, counted in 16-byte units, followed by that much metadata
+//! such as `StreamTitle='Artist - Song';`, padded with zeros.
+
+use std::io::{self, Read};
+
+/// Bytes of audio between two metadata blocks.
+pub const METAINT: usize = 16 * 1024;
+
+/// Longest metadata block the length byte can describe.
+const MAX_METADATA_LEN: usize = 255 * 16;
+
+/// Audio source that knows the title of what it's playing.
+pub trait StreamTitle {
+    fn stream_title(&self) -> Option<String>;
+}
+
+/// Reader inserting ICY metadata blocks into the audio of `inner`.
+///
+/// The title is only sent when it changes; the other blocks are empty.
+pub struct IcyStream<R> {
+    inner: R,
+    metaint: usize,
+    until_metadata: usize,
+    metadata: Vec<u8>,
+    metadata_pos: usize,
+    sent_title: Option<String>,
+}
+
+impl<R> IcyStream<R>
+where
+    R: Read + StreamTitle,
+{
+    pub fn new(inner: R, metaint: usize) -> Self {
+        IcyStream {
+            inner,
+            metaint,
+            until_metadata: metaint,
+            metadata: Vec::new(),
+            metadata_pos: 0,
+            sent_title: None,
+        }
+    }
+
+    fn next_metadata(&mut self) {
+        let title = self.inner.stream_title();
+        self.metadata.clear();
+        self.metadata_pos = 0;
+
+        match title {
+            Some(ref title) if self.sent_title.as_ref() != Some(title) => {
+                // Quotes would end the title early in most clients.
+                let escaped = title.replace('\'', "\u{2019}");
+                let max_len = MAX_METADATA_LEN - "StreamTitle='';".len();
+                let text = format!("StreamTitle='{}';", truncate(&escaped, max_len));
+                let text = text.into_bytes();
+                let blocks = text.len().div_ceil(16);
+                self.metadata.push(blocks as u8);
+                self.metadata.extend_from_slice(&text);
+                self.metadata.resize(1 + blocks * 16, 0);
+                self.sent_title = Some(title.clone());
+            }
+            _ => self.metadata.push(0),
+        }
+    }
+}
+
+/// Cuts `text` to at most `max_len` bytes without splitting a character.
+fn truncate(text: &str, max_len: usize) -> &str {
+    let end = text
+        .char_indices()
+        .map(|(index, c)| index + c.len_utf8())
+        .take_while(|&end| end <= max_len)
+        .last()
+        .unwrap_or(0);
+    &text[..end]
+}
+
+impl<R> Read for IcyStream<R>
+where
+    R: Read + StreamTitle,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.metadata_pos < self.metadata.len() {
+            let pending = &self.metadata[self.metadata_pos..];
+            let len = pending.len().min(buf.len());
+            buf[..len].copy_from_slice(&pending[..len]);
+            self.metadata_pos += len;
+            return Ok(len);
+        }
+
+        if self.until_metadata == 0 {
+            self.next_metadata();
+            self.until_metadata = self.metaint;
+            return self.read(buf);
+        }
+
+        let len = buf.len().min(self.until_metadata);
+        let read = self.inner.read(&mut buf[..len])?;
+        self.until_metadata -= read;
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IcyStream, StreamTitle, MAX_METADATA_LEN};
+    use std::io::{Cursor, Read};
+
+    struct Titled(Cursor<Vec<u8>>, String);
+
+    impl Read for Titled {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.0.read(buf)
+        }
+    }
+
+    impl StreamTitle for Titled {
+        fn stream_title(&self) -> Option<String> {
+            Some(self.1.clone())
+        }
+    }
+
+    #[test]
+    fn interleaves_title_once() {
+        let audio = Titled(
+            Cursor::new(b"abcdefghij".to_vec()),
+            "Artist - It's".to_owned(),
+        );
+        let mut output = Vec::new();
+        IcyStream::new(audio, 4).read_to_end(&mut output).unwrap();
+
+        let title = "StreamTitle='Artist - It\u{2019}s';".as_bytes();
+        let mut expected = b"abcd\x02".to_vec();
+        expected.extend_from_slice(title);
+        expected.resize(5 + 32, 0);
+        expected.extend_from_slice(b"efgh\x00ij");
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn truncates_long_titles_between_characters() {
+        let audio = Titled(
+            Cursor::new(b"ab".to_vec()),
+            "\u{e9}".repeat(MAX_METADATA_LEN),
+        );
+        let mut output = Vec::new();
+        IcyStream::new(audio, 1).read_to_end(&mut output).unwrap();
+
+        let blocks = output[1] as usize;
+        let metadata = &output[2..2 + blocks * 16];
+        let text = std::str::from_utf8(metadata)
+            .unwrap()
+            .trim_end_matches('\0');
+        assert!(text.len() <= MAX_METADATA_LEN);
+        assert!(text.starts_with("StreamTitle='\u{e9}"));
+        assert!(text.ends_with("\u{e9}';"));
+    }
+}
@@ -0,0 +1,362 @@
+//! Serves Pandora stations as internet radio over local HTTP.
+//!
+//! `GET /stations.m3u` returns a playlist of every station, and
+//! `GET /station/{id}` streams a station's tracks back to back, with ICY
+//! `StreamTitle` metadata for clients that ask for it. Streams are MP3,
+//! unless the first track only comes as AAC; tracks in another format
+//! than the stream's are skipped. Logs in once with the
+//! `PANDORA_USERNAME` and `PANDORA_PASSWORD` environment variables.
+
+extern crate pandora_rs2;
+extern crate tiny_http;
+
+mod icy;
+
+use icy::{IcyStream, StreamTitle, METAINT};
+
+use pandora_rs2::audio::AudioStream;
+use pandora_rs2::playlist::{Playlist, Track, Tracks};
+use pandora_rs2::Pandora;
+
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use std::env;
+use std::error::Error as StdError;
+use std::io::{self, Read};
+use std::process;
+use std::sync::Arc;
+use std::thread;
+
+const USAGE: &str = "\
+Usage: pandora-radio [--bind ADDR]
+
+Options:
+    --bind ADDR     Address to listen on (default: 127.0.0.1:8000); use
+                    0.0.0.0:8000 to serve the whole LAN
+";
+
+const DEFAULT_BIND: &str = "127.0.0.1:8000";
+
+/// Tracks in a row that may fail to open before a stream gives up.
+const MAX_FAILED_TRACKS: usize = 3;
+
+fn main() {
+    let bind = match parse_args(env::args().skip(1)) {
+        Ok(bind) => bind,
+        Err(message) => {
+            eprint!("pandora-radio: {}\n\n{}", message, USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Err(err) = run(&bind) {
+        eprintln!("pandora-radio: {}", err);
+        process::exit(1);
+    }
+}
+
+fn parse_args<I>(mut args: I) -> Result<String, String>
+where
+    I: Iterator<Item = String>,
+{
+    let mut bind = DEFAULT_BIND.to_owned();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--bind" => bind = args.next().ok_or("--bind needs a value")?,
+            "-h" | "--help" => {
+                print!("{}", USAGE);
+                process::exit(0);
+            }
+            _ => return Err(format!("unexpected argument {:?}", arg)),
+        }
+    }
+    Ok(bind)
+}
+
+fn run(bind: &str) -> Result<(), Box<dyn StdError + Send + Sync>> {
+    let username = env::var("PANDORA_USERNAME").map_err(|_| "PANDORA_USERNAME is not set")?;
+    let password = env::var("PANDORA_PASSWORD").map_err(|_| "PANDORA_PASSWORD is not set")?;
+    let pandora = Arc::new(Pandora::new(&username, &password)?);
+
+    let server = Server::http(bind)?;
+    eprintln!("pandora-radio: listening on http://{}/stations.m3u", bind);
+
+    for request in server.incoming_requests() {
+        let pandora = Arc::clone(&pandora);
+        // Streams last as long as someone listens, so each gets a thread.
+        thread::spawn(move || {
+            if let Err(err) = handle(&pandora, request) {
+                eprintln!("pandora-radio: {}", err);
+            }
+        });
+    }
+    Ok(())
+}
+
+fn handle(pandora: &Pandora, request: Request) -> io::Result<()> {
+    if *request.method() != Method::Get {
+        return request.respond(Response::from_string("method not allowed").with_status_code(405));
+    }
+
+    let url = request.url().to_owned();
+    let path = url.split('?').next().unwrap_or_default();
+    if path == "/stations.m3u" {
+        return respond_m3u(pandora, request);
+    }
+    match station_id(path) {
+        Some(station_id) => respond_station(pandora, request, station_id),
+        None => request.respond(Response::from_string("not found").with_status_code(404)),
+    }
+}
+
+/// Returns the station ID of a `/station/{id}` path.
+fn station_id(path: &str) -> Option<&str> {
+    path.strip_prefix("/station/")
+        .filter(|id| !id.is_empty() && !id.contains('/'))
+}
+
+fn respond_m3u(pandora: &Pandora, request: Request) -> io::Result<()> {
+    let stations = match pandora.stations().list() {
+        Ok(stations) => stations,
+        Err(err) => return respond_error(request, &err),
+    };
+
+    let host = header(&request, "Host").unwrap_or_else(|| "localhost".to_owned());
+    let mut m3u = String::from("#EXTM3U\n");
+    for station in stations {
+        m3u.push_str(&format!(
+            "#EXTINF:-1,{}\nhttp://{}/station/{}\n",
+            station.station_name, host, station.station_id
+        ));
+    }
+
+    let response = Response::from_string(m3u).with_header(content_type("audio/x-mpegurl"));
+    request.respond(response)
+}
+
+fn respond_station(pandora: &Pandora, request: Request, station_id: &str) -> io::Result<()> {
+    let station = match pandora.stations().station(&station_id.to_owned()) {
+        Ok(station) => station,
+        Err(err) => return respond_error(request, &err),
+    };
+    let tracks = Playlist::new(pandora, &station)
+        .tracks()
+        .skip_ads(true)
+        .skip_expired(true);
+    let mut audio = StationAudio {
+        pandora,
+        tracks,
+        stream: None,
+        title: None,
+        content_type: None,
+    };
+    // The first track sets the format of the stream.
+    match audio.open_next() {
+        Ok(true) => {}
+        Ok(false) => {
+            let response = Response::from_string("station has no tracks").with_status_code(503);
+            return request.respond(response);
+        }
+        Err(err) => {
+            let response = Response::from_string(err.to_string()).with_status_code(502);
+            return request.respond(response);
+        }
+    }
+
+    let mut headers = vec![
+        content_type(audio.content_type.as_deref().unwrap_or(MP3)),
+        header_value("icy-name", &station.station_name),
+    ];
+    let wants_metadata = header(&request, "Icy-MetaData").is_some_and(|value| value == "1");
+    if wants_metadata {
+        headers.push(header_value("icy-metaint", &METAINT.to_string()));
+        let audio = IcyStream::new(audio, METAINT);
+        request.respond(Response::new(200.into(), headers, audio, None, None))
+    } else {
+        request.respond(Response::new(200.into(), headers, audio, None, None))
+    }
+}
+
+fn respond_error(request: Request, err: &pandora_rs2::error::Error) -> io::Result<()> {
+    let status = if err.api_code().is_some() { 502 } else { 500 };
+    request.respond(Response::from_string(err.to_string()).with_status_code(status))
+}
+
+/// The tracks of a station read back to back.
+struct StationAudio<'a> {
+    pandora: &'a Pandora,
+    tracks: Tracks<'a>,
+    stream: Option<AudioStream>,
+    title: Option<String>,
+    /// Content type of the stream, set by its first track.
+    content_type: Option<String>,
+}
+
+impl<'a> StationAudio<'a> {
+    /// Opens the next track, returning false once the station ran out.
+    /// Tracks that can't be fetched or opened are skipped, up to
+    /// `MAX_FAILED_TRACKS` in a row.
+    fn open_next(&mut self) -> io::Result<bool> {
+        let mut failures = 0;
+        loop {
+            let err = match self.tracks.next() {
+                Some(Ok(track)) => match self.open(&track) {
+                    Some(Ok(())) => return Ok(true),
+                    Some(Err(err)) => err,
+                    None => continue,
+                },
+                Some(Err(err)) => err.to_string(),
+                None => return Ok(false),
+            };
+            failures += 1;
+            if failures >= MAX_FAILED_TRACKS {
+                return Err(io::Error::other(err));
+            }
+            eprintln!("pandora-radio: skipping track: {}", err);
+        }
+    }
+
+    /// Opens the audio of `track`, or returns None if it has none.
+    fn open(&mut self, track: &Track) -> Option<Result<(), String>> {
+        let (url, expected_type) = audio_url(track)?;
+        let stream = match AudioStream::open_url(self.pandora, &url) {
+            Ok(stream) => stream,
+            Err(err) => return Some(Err(err.to_string())),
+        };
+        let actual_type = stream.content_type().unwrap_or(expected_type).to_owned();
+        match self.content_type {
+            Some(ref content_type) if *content_type != actual_type => {
+                Some(Err(format!("{} in a {} stream", actual_type, content_type)))
+            }
+            _ => {
+                self.content_type = Some(actual_type);
+                self.stream = Some(stream);
+                self.title = Some(track_title(track));
+                Some(Ok(()))
+            }
+        }
+    }
+}
+
+impl<'a> Read for StationAudio<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if let Some(ref mut stream) = self.stream {
+                match stream.read(buf)? {
+                    0 => self.stream = None,
+                    read => return Ok(read),
+                }
+            }
+            if !self.open_next()? {
+                return Ok(0);
+            }
+        }
+    }
+}
+
+impl<'a> StreamTitle for StationAudio<'a> {
+    fn stream_title(&self) -> Option<String> {
+        self.title.clone()
+    }
+}
+
+const MP3: &str = "audio/mpeg";
+const AAC: &str = "audio/aac";
+
+/// Returns the URL of the track's audio and its content type. MP3 comes
+/// first: the `audioUrlMap` entries are mostly AAC, which fewer radio
+/// clients play.
+fn audio_url(track: &Track) -> Option<(String, &'static str)> {
+    if let Some(ref url) = track.additional_audio_url {
+        return Some((url.clone(), MP3));
+    }
+    let audio = track.track_audio.as_ref()?.best()?;
+    let content_type = match audio.encoding.as_str() {
+        "mp3" | "mp3-hifi" => MP3,
+        _ => AAC,
+    };
+    Some((audio.audio_url.clone(), content_type))
+}
+
+fn track_title(track: &Track) -> String {
+    match (&track.artist_name, &track.song_name) {
+        (Some(artist), Some(song)) => format!("{} - {}", artist, song),
+        (None, Some(song)) => song.clone(),
+        (Some(artist), None) => artist.clone(),
+        (None, None) => String::new(),
+    }
+}
+
+fn header(request: &Request, name: &'static str) -> Option<String> {
+    request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv(name))
+        .map(|header| header.value.as_str().to_owned())
+}
+
+fn header_value(name: &str, value: &str) -> Header {
+    // Header values must be ASCII.
+    let value: String = value
+        .chars()
+        .map(|c| {
+            if c.is_ascii() && !c.is_ascii_control() {
+                c
+            } else {
+                '?'
+            }
+        })
+        .collect();
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).unwrap()
+}
+
+fn content_type(value: &str) -> Header {
+    header_value("Content-Type", value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{audio_url, station_id, AAC, MP3};
+
+    use pandora_rs2::playlist::Track;
+
+    use serde_json::json;
+
+    fn track(value: serde_json::Value) -> Track {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn station_paths() {
+        assert_eq!(station_id("/station/42"), Some("42"));
+        assert_eq!(station_id("/station/"), None);
+        assert_eq!(station_id("/station/42/more"), None);
+        assert_eq!(station_id("/stations.m3u"), None);
+    }
+
+    #[test]
+    fn prefers_mp3_and_labels_the_fallback() {
+        let aac = json!({
+            "highQuality": {
+                "bitrate": "64",
+                "encoding": "aacplus",
+                "audioUrl": "http://audio.example/high",
+                "protocol": "http",
+            },
+        });
+        let mp3 =
+            track(json!({ "additionalAudioUrl": "http://audio.example/mp3", "audioUrlMap": aac }));
+        assert_eq!(
+            audio_url(&mp3),
+            Some(("http://audio.example/mp3".to_owned(), MP3))
+        );
+
+        let fallback = track(json!({ "audioUrlMap": aac }));
+        assert_eq!(
+            audio_url(&fallback),
+            Some(("http://audio.example/high".to_owned(), AAC))
+        );
+
+        assert_eq!(audio_url(&track(json!({ "adToken": "ad" }))), None);
+    }
+}
@@ -0,0 +1,394 @@
+//! JSON-lines bridge for driving the library from other languages.
+//!
+//! Each line on stdin is a command like
+//! `{"id": 1, "method": "stations.list", "params": {}}`. Each line on
+//! stdout is either a reply, `{"id": 1, "result": ...}` or
+//! `{"id": 1, "error": {"code": "InvalidAuthToken", "number": 1001,
+//! "message": "..."}}`, or an event such as `{"event": "loggedIn"}`.
+
+use pandora_rs2::error::{self, Error};
+use pandora_rs2::music::Music;
+use pandora_rs2::stations::Seed;
+use pandora_rs2::Pandora;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use std::io::{self, BufRead, Write};
+
+/// A command read from stdin.
+#[derive(Deserialize)]
+struct Command {
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+/// Error sent back for a failed command.
+#[derive(Serialize)]
+struct BridgeError {
+    /// `ApiErrorCode` name for API errors, otherwise the kind of failure.
+    code: &'static str,
+    /// Numeric Pandora error code, for API errors.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    number: Option<u32>,
+    message: String,
+}
+
+impl BridgeError {
+    fn new(code: &'static str, message: String) -> Self {
+        BridgeError {
+            code,
+            number: None,
+            message,
+        }
+    }
+}
+
+impl From<Error> for BridgeError {
+    fn from(err: Error) -> Self {
+        let code = match err {
+            Error::Api { ref code, .. } => {
+                return BridgeError {
+                    code: code.name(),
+                    number: code.code(),
+                    message: err.to_string(),
+                }
+            }
+            Error::Io(_) => "Io",
+            Error::Codec(_) => "Codec",
+            Error::Http(_) => "Http",
+            Error::Crypt(_) => "Crypt",
+            Error::InvalidSyncTime(_) => "InvalidSyncTime",
+            Error::MissingResult { .. } => "MissingResult",
+            Error::Decode { .. } => "Decode",
+            Error::SkipLimitReached { .. } => "SkipLimitReached",
+        };
+        BridgeError::new(code, err.to_string())
+    }
+}
+
+type BridgeResult = Result<Value, BridgeError>;
+
+#[derive(Deserialize)]
+struct LoginParams {
+    username: String,
+    password: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StationParams {
+    station_id: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CreateStationParams {
+    music_token: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RenameStationParams {
+    station_id: String,
+    name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AddSeedParams {
+    station_id: String,
+    music_token: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RemoveSeedParams {
+    seed_id: String,
+}
+
+#[derive(Deserialize)]
+struct SearchParams {
+    text: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RateParams {
+    station_id: String,
+    track_token: String,
+    is_positive: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TiredParams {
+    station_id: String,
+    track_token: String,
+}
+
+/// Logs in with a username and password.
+type Login = fn(&str, &str) -> error::Result<Pandora>;
+
+struct Bridge<W> {
+    out: W,
+    login: Login,
+    pandora: Option<Pandora>,
+}
+
+/// Serves commands from stdin until it's closed.
+pub fn run() -> io::Result<()> {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    serve(stdin.lock(), stdout.lock(), Pandora::new)
+}
+
+/// Serves the commands of `input` until it ends, writing the replies to
+/// `out` and logging in with `login`.
+fn serve<R, W>(input: R, out: W, login: Login) -> io::Result<()>
+where
+    R: BufRead,
+    W: Write,
+{
+    let mut bridge = Bridge {
+        out,
+        login,
+        pandora: None,
+    };
+
+    bridge.emit(&json!({ "event": "ready" }))?;
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let reply = match serde_json::from_str::<Command>(&line) {
+            Ok(command) => {
+                let id = command.id.clone();
+                match bridge.handle(command) {
+                    Ok(result) => json!({ "id": id, "result": result }),
+                    Err(err) => json!({ "id": id, "error": err }),
+                }
+            }
+            Err(err) => json!({
+                "id": Value::Null,
+                "error": BridgeError::new("ParseError", err.to_string()),
+            }),
+        };
+        bridge.emit(&reply)?;
+    }
+    Ok(())
+}
+
+impl<W> Bridge<W>
+where
+    W: Write,
+{
+    fn emit(&mut self, value: &Value) -> io::Result<()> {
+        writeln!(self.out, "{}", value)?;
+        self.out.flush()
+    }
+
+    fn handle(&mut self, command: Command) -> BridgeResult {
+        match command.method.as_str() {
+            "login" => {
+                let params: LoginParams = params(command.params)?;
+                self.pandora = Some((self.login)(&params.username, &params.password)?);
+                self.emit(&json!({ "event": "loggedIn" }))
+                    .map_err(|e| BridgeError::new("Io", e.to_string()))?;
+                Ok(json!(true))
+            }
+            "logout" => {
+                self.pandora = None;
+                Ok(json!(true))
+            }
+            method => {
+                let pandora = self.pandora.as_ref().ok_or_else(|| {
+                    BridgeError::new("NotLoggedIn", "call login first".to_owned())
+                })?;
+                dispatch(pandora, method, command.params)
+            }
+        }
+    }
+}
+
+fn dispatch(pandora: &Pandora, method: &str, params_value: Value) -> BridgeResult {
+    let stations = pandora.stations();
+    match method {
+        "stations.list" => result(stations.list()?),
+        "stations.get" => {
+            let params: StationParams = params(params_value)?;
+            result(stations.station(&params.station_id)?)
+        }
+        "stations.create" => {
+            let params: CreateStationParams = params(params_value)?;
+            result(stations.create(&params.music_token)?)
+        }
+        "stations.rename" => {
+            let params: RenameStationParams = params(params_value)?;
+            result(stations.rename(&params.station_id, &params.name)?)
+        }
+        "stations.delete" => {
+            let params: StationParams = params(params_value)?;
+            stations.delete(&params.station_id)?;
+            Ok(json!(true))
+        }
+        "seeds.add" => {
+            let params: AddSeedParams = params(params_value)?;
+            result(stations.add_seed(&params.station_id, &params.music_token)?)
+        }
+        "seeds.remove" => {
+            let params: RemoveSeedParams = params(params_value)?;
+            stations.remove_seed(&Seed {
+                seed_id: params.seed_id,
+            })?;
+            Ok(json!(true))
+        }
+        "search" => {
+            let params: SearchParams = params(params_value)?;
+            result(Music::new(pandora).search(&params.text)?)
+        }
+        "playlist.get" => {
+            let params: StationParams = params(params_value)?;
+            result(stations.playlist(&params.station_id).list()?)
+        }
+        "rate" => {
+            let params: RateParams = params(params_value)?;
+            stations
+                .playlist(&params.station_id)
+                .rate(params.track_token, params.is_positive)?;
+            Ok(json!(true))
+        }
+        "tired" => {
+            let params: TiredParams = params(params_value)?;
+            stations
+                .playlist(&params.station_id)
+                .sleep(params.track_token)?;
+            Ok(json!(true))
+        }
+        _ => Err(BridgeError::new(
+            "UnknownMethod",
+            format!("unknown method {:?}", method),
+        )),
+    }
+}
+
+fn params<T>(params: Value) -> Result<T, BridgeError>
+where
+    T: DeserializeOwned,
+{
+    // Commands without parameters may omit them or send null.
+    let params = if params.is_null() { json!({}) } else { params };
+    serde_json::from_value(params).map_err(|e| BridgeError::new("InvalidParams", e.to_string()))
+}
+
+fn result<T>(value: T) -> BridgeResult
+where
+    T: Serialize,
+{
+    serde_json::to_value(value).map_err(|e| BridgeError::new("Codec", e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::serve;
+    use crate::testing::login;
+
+    use serde_json::{json, Value};
+
+    /// Feeds `input` to a bridge and returns what it wrote, a JSON value
+    /// per line.
+    fn round_trip(input: &str) -> Vec<Value> {
+        let mut out = Vec::new();
+        serve(input.as_bytes(), &mut out, login).unwrap();
+        String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    /// Returns the commands as JSON lines.
+    fn lines(commands: &[Value]) -> String {
+        commands
+            .iter()
+            .map(|command| format!("{}\n", command))
+            .collect()
+    }
+
+    fn login_command(id: u32) -> Value {
+        json!({
+            "id": id,
+            "method": "login",
+            "params": { "username": "me", "password": "hunter2" },
+        })
+    }
+
+    #[test]
+    fn replies_to_each_command_in_order() {
+        let mut replies = round_trip(&lines(&[
+            json!({ "id": 1, "method": "stations.list" }),
+            login_command(2),
+            json!({ "id": 3, "method": "stations.list", "params": null }),
+            json!({ "id": "four", "method": "stations.get", "params": { "stationId": "9" } }),
+            json!({ "id": 5, "method": "logout" }),
+            json!({ "id": 6, "method": "stations.list" }),
+        ]));
+
+        let message = replies[5]["error"]
+            .as_object_mut()
+            .unwrap()
+            .remove("message")
+            .unwrap();
+        assert!(message.as_str().unwrap().contains("no such station"));
+        let not_logged_in = json!({ "code": "NotLoggedIn", "message": "call login first" });
+        assert_eq!(
+            replies,
+            [
+                json!({ "event": "ready" }),
+                json!({ "id": 1, "error": not_logged_in }),
+                json!({ "event": "loggedIn" }),
+                json!({ "id": 2, "result": true }),
+                json!({ "id": 3, "result": [{ "stationId": "1", "stationName": "Jazz" }] }),
+                json!({
+                    "id": "four",
+                    "error": { "code": "StationDoesNotExists", "number": 1006 },
+                }),
+                json!({ "id": 5, "result": true }),
+                json!({ "id": 6, "error": not_logged_in }),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_malformed_commands() {
+        let input = format!(
+            "not json\n\n{}",
+            lines(&[
+                login_command(1),
+                json!({ "id": 2, "method": "stations.get" }),
+                json!({ "id": 3, "method": "stations.frobnicate" }),
+            ])
+        );
+        let replies = round_trip(&input);
+
+        let errors: Vec<(&Value, &Value)> = replies
+            .iter()
+            .filter(|reply| reply.get("error").is_some())
+            .map(|reply| (&reply["id"], &reply["error"]["code"]))
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                (&Value::Null, &json!("ParseError")),
+                (&json!(2), &json!("InvalidParams")),
+                (&json!(3), &json!("UnknownMethod")),
+            ]
+        );
+    }
+}
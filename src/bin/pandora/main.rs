@@ -0,0 +1,529 @@
+//! Command-line client for managing a Pandora account.
+//!
+//! Credentials are read from the `PANDORA_USERNAME` and `PANDORA_PASSWORD`
+//! environment variables, or else from a JSON config file with `username`
+//! and `password` keys (`--config`, `$PANDORA_CONFIG`, or
+//! `~/.config/pandora/config.json`).
+//!
+//! `--record FILE` saves the session to a cassette, with tokens,
+//! passwords and account details redacted, and `--replay FILE` runs a
+//! command against one without a network or credentials.
+
+extern crate pandora_rs2;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+
+mod bridge;
+#[cfg(test)]
+mod testing;
+
+use pandora_rs2::cassette::{Recorder, Replay, ReplayMode};
+use pandora_rs2::music::{Music, SearchResults};
+use pandora_rs2::playlist::Track;
+use pandora_rs2::stations::{Seed, Station};
+use pandora_rs2::{EndpointStrategy, Pandora};
+
+use serde::Serialize;
+use serde_json::Value;
+
+use std::env;
+use std::error::Error as StdError;
+use std::fs::File;
+use std::path::PathBuf;
+use std::process;
+
+const USAGE: &str = "\
+Usage: pandora [--json] [--config FILE] [--record FILE | --replay FILE] COMMAND [ARGS...]
+
+Commands:
+    login                                   Checks the credentials
+    stations list                           Lists the stations
+    stations create MUSIC_TOKEN             Creates a station from a song or artist
+    stations rename STATION_ID NAME         Renames a station
+    stations delete STATION_ID              Deletes a station
+    seeds add STATION_ID MUSIC_TOKEN        Adds a seed to a station
+    seeds remove SEED_ID                    Removes a seed from its station
+    search TEXT...                          Searches songs and artists
+    playlist STATION_ID                     Prints the next tracks of a station
+    rate STATION_ID TRACK_TOKEN up|down     Rates a track
+    bridge                                  Serves JSON-lines commands on stdin
+
+Options:
+    --json          Prints JSON instead of tables
+    --config FILE   Reads credentials from FILE
+    --record FILE   Records the API calls to the cassette FILE
+    --replay FILE   Answers the API calls from the cassette FILE
+";
+
+type CliResult = Result<(), Box<dyn StdError>>;
+
+struct Options {
+    json: bool,
+    config: Option<PathBuf>,
+    record: Option<PathBuf>,
+    replay: Option<PathBuf>,
+    command: Command,
+}
+
+/// A command and its arguments, checked before logging in.
+#[derive(Debug, PartialEq)]
+enum Command {
+    Login,
+    ListStations,
+    CreateStation {
+        music_token: String,
+    },
+    RenameStation {
+        station_id: String,
+        name: String,
+    },
+    DeleteStation {
+        station_id: String,
+    },
+    AddSeed {
+        station_id: String,
+        music_token: String,
+    },
+    RemoveSeed {
+        seed_id: String,
+    },
+    Search {
+        text: String,
+    },
+    Playlist {
+        station_id: String,
+    },
+    Rate {
+        station_id: String,
+        track_token: String,
+        is_positive: bool,
+    },
+    Bridge,
+}
+
+impl Command {
+    fn parse(args: &[String]) -> Result<Command, String> {
+        let args: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+        let command = match args[..] {
+            ["login"] => Command::Login,
+            ["stations", "list"] => Command::ListStations,
+            ["stations", "create", music_token] => Command::CreateStation {
+                music_token: music_token.to_owned(),
+            },
+            ["stations", "rename", station_id, ref name @ ..] if !name.is_empty() => {
+                Command::RenameStation {
+                    station_id: station_id.to_owned(),
+                    name: name.join(" "),
+                }
+            }
+            ["stations", "delete", station_id] => Command::DeleteStation {
+                station_id: station_id.to_owned(),
+            },
+            ["seeds", "add", station_id, music_token] => Command::AddSeed {
+                station_id: station_id.to_owned(),
+                music_token: music_token.to_owned(),
+            },
+            ["seeds", "remove", seed_id] => Command::RemoveSeed {
+                seed_id: seed_id.to_owned(),
+            },
+            ["search", ref text @ ..] if !text.is_empty() => Command::Search {
+                text: text.join(" "),
+            },
+            ["playlist", station_id] => Command::Playlist {
+                station_id: station_id.to_owned(),
+            },
+            ["rate", station_id, track_token, rating] => Command::Rate {
+                station_id: station_id.to_owned(),
+                track_token: track_token.to_owned(),
+                is_positive: match rating {
+                    "up" => true,
+                    "down" => false,
+                    _ => return Err(format!("rating must be up or down, not {:?}", rating)),
+                },
+            },
+            ["bridge"] => Command::Bridge,
+            [] => return Err("missing command".to_owned()),
+            _ => return Err(format!("unknown command {:?}", args.join(" "))),
+        };
+        Ok(command)
+    }
+}
+
+fn main() {
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprint!("pandora: {}\n\n{}", message, USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Err(err) = run(options) {
+        eprintln!("pandora: {}", err);
+        process::exit(1);
+    }
+}
+
+fn parse_args<I>(mut args: I) -> Result<Options, String>
+where
+    I: Iterator<Item = String>,
+{
+    let mut options = Options {
+        json: false,
+        config: None,
+        record: None,
+        replay: None,
+        command: Command::Login,
+    };
+    let mut command = Vec::new();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" if command.is_empty() => options.json = true,
+            "--config" if command.is_empty() => {
+                let path = args.next().ok_or("--config needs a value")?;
+                options.config = Some(PathBuf::from(path));
+            }
+            "--record" if command.is_empty() => {
+                let path = args.next().ok_or("--record needs a value")?;
+                options.record = Some(PathBuf::from(path));
+            }
+            "--replay" if command.is_empty() => {
+                let path = args.next().ok_or("--replay needs a value")?;
+                options.replay = Some(PathBuf::from(path));
+            }
+            "-h" | "--help" if command.is_empty() => {
+                print!("{}", USAGE);
+                process::exit(0);
+            }
+            _ => command.push(arg),
+        }
+    }
+
+    options.command = Command::parse(&command)?;
+    if options.record.is_some() && options.replay.is_some() {
+        return Err("--record and --replay can't be used together".to_owned());
+    }
+    Ok(options)
+}
+
+fn run(options: Options) -> CliResult {
+    // The bridge logs in when asked to, with the credentials it's sent.
+    if options.command == Command::Bridge {
+        return Ok(bridge::run()?);
+    }
+
+    let pandora = login(&options)?;
+    let json = options.json;
+
+    match options.command {
+        Command::Login => {
+            if json {
+                print_json(&serde_json::json!({ "loggedIn": true }))
+            } else {
+                println!("Logged in.");
+                Ok(())
+            }
+        }
+
+        Command::ListStations => {
+            let stations = pandora.stations().list()?;
+            if json {
+                return print_json(&stations);
+            }
+            print_stations(&stations);
+            Ok(())
+        }
+        Command::CreateStation { music_token } => {
+            let station = pandora.stations().create(&music_token)?;
+            if json {
+                return print_json(&station);
+            }
+            print_stations(&[station]);
+            Ok(())
+        }
+        Command::RenameStation { station_id, name } => {
+            let station = pandora.stations().rename(&station_id, &name)?;
+            if json {
+                return print_json(&station);
+            }
+            print_stations(&[station]);
+            Ok(())
+        }
+        Command::DeleteStation { station_id } => {
+            pandora.stations().delete(&station_id)?;
+            print_done(json)
+        }
+
+        Command::AddSeed {
+            station_id,
+            music_token,
+        } => {
+            let seed = pandora.stations().add_seed(&station_id, &music_token)?;
+            if json {
+                return print_json(&seed);
+            }
+            println!("{}", seed.seed_id);
+            Ok(())
+        }
+        Command::RemoveSeed { seed_id } => {
+            pandora.stations().remove_seed(&Seed { seed_id })?;
+            print_done(json)
+        }
+
+        Command::Search { text } => {
+            let results = Music::new(&pandora).search(&text)?;
+            if json {
+                return print_json(&results);
+            }
+            print_search_results(&results);
+            Ok(())
+        }
+
+        Command::Playlist { station_id } => {
+            let tracks = pandora.stations().playlist(&station_id).list()?;
+            if json {
+                return print_json(&tracks);
+            }
+            print_tracks(&tracks);
+            Ok(())
+        }
+
+        Command::Rate {
+            station_id,
+            track_token,
+            is_positive,
+        } => {
+            pandora
+                .stations()
+                .playlist(&station_id)
+                .rate(track_token, is_positive)?;
+            print_done(json)
+        }
+
+        Command::Bridge => unreachable!("the bridge runs without logging in"),
+    }
+}
+
+/// Logs in with the credentials from the environment or the config file,
+/// recording or replaying the session if asked to.
+fn login(options: &Options) -> Result<Pandora, Box<dyn StdError>> {
+    let strategy = EndpointStrategy::default();
+    if let Some(ref path) = options.replay {
+        // Replayed logins succeed whatever the credentials.
+        let (username, password) = credentials(options.config.clone()).unwrap_or_default();
+        let replay = Replay::open(path, ReplayMode::InOrder)?;
+        return Ok(Pandora::with_transport(
+            &username, &password, strategy, replay,
+        )?);
+    }
+
+    let (username, password) = credentials(options.config.clone())?;
+    match options.record {
+        Some(ref path) => {
+            let recorder = Recorder::create(path, reqwest::Client::new())?;
+            Ok(Pandora::with_transport(
+                &username, &password, strategy, recorder,
+            )?)
+        }
+        None => Ok(Pandora::with_endpoints(&username, &password, strategy)?),
+    }
+}
+
+/// Reads the credentials from the environment or the config file.
+fn credentials(config: Option<PathBuf>) -> Result<(String, String), Box<dyn StdError>> {
+    if let (Ok(username), Ok(password)) =
+        (env::var("PANDORA_USERNAME"), env::var("PANDORA_PASSWORD"))
+    {
+        return Ok((username, password));
+    }
+
+    let path = config
+        .or_else(|| env::var_os("PANDORA_CONFIG").map(PathBuf::from))
+        .or_else(default_config_path)
+        .ok_or("no credentials: set PANDORA_USERNAME and PANDORA_PASSWORD")?;
+    let file = File::open(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let config: Value = serde_json::from_reader(file)?;
+
+    let field = |name: &str| {
+        config
+            .get(name)
+            .and_then(|value| value.as_str())
+            .map(|value| value.to_owned())
+            .ok_or_else(|| format!("{}: missing {:?}", path.display(), name))
+    };
+    Ok((field("username")?, field("password")?))
+}
+
+fn default_config_path() -> Option<PathBuf> {
+    let config_dir = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_dir.join("pandora").join("config.json"))
+}
+
+fn print_json<T>(value: &T) -> CliResult
+where
+    T: Serialize,
+{
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+fn print_done(json: bool) -> CliResult {
+    if json {
+        print_json(&serde_json::json!({ "ok": true }))
+    } else {
+        Ok(())
+    }
+}
+
+fn print_stations(stations: &[Station]) {
+    print_table(
+        &["ID", "NAME"],
+        stations
+            .iter()
+            .map(|station| vec![station.station_id.clone(), station.station_name.clone()])
+            .collect(),
+    );
+}
+
+fn print_search_results(results: &SearchResults) {
+    if !results.artists().is_empty() {
+        print_table(
+            &["ARTIST", "MUSIC TOKEN"],
+            results
+                .artists()
+                .iter()
+                .map(|artist| vec![artist.artist_name.clone(), artist.music_token.clone()])
+                .collect(),
+        );
+    }
+    if !results.songs().is_empty() {
+        if !results.artists().is_empty() {
+            println!();
+        }
+        print_table(
+            &["SONG", "ARTIST", "MUSIC TOKEN"],
+            results
+                .songs()
+                .iter()
+                .map(|song| {
+                    vec![
+                        song.song_name.clone(),
+                        song.artist_name.clone(),
+                        song.music_token.clone(),
+                    ]
+                })
+                .collect(),
+        );
+    }
+}
+
+fn print_tracks(tracks: &[Track]) {
+    let text = |value: &Option<String>| value.clone().unwrap_or_default();
+    print_table(
+        &["SONG", "ARTIST", "ALBUM", "TRACK TOKEN", "URL"],
+        tracks
+            .iter()
+            .filter(|track| !track.is_ad())
+            .map(|track| {
+                let url = track
+                    .additional_audio_url
+                    .clone()
+                    .or_else(|| {
+                        track
+                            .track_audio
+                            .as_ref()
+                            .and_then(|audio| audio.best())
+                            .map(|audio| audio.audio_url.clone())
+                    })
+                    .unwrap_or_default();
+                vec![
+                    text(&track.song_name),
+                    text(&track.artist_name),
+                    text(&track.album_name),
+                    text(&track.track_token),
+                    url,
+                ]
+            })
+            .collect(),
+    );
+}
+
+/// Prints rows as left-aligned columns.
+fn print_table(headers: &[&str], rows: Vec<Vec<String>>) {
+    let mut widths: Vec<usize> = headers
+        .iter()
+        .map(|header| header.chars().count())
+        .collect();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let print_row = |cells: Vec<&str>| {
+        let last = cells.len() - 1;
+        let mut line = String::new();
+        for (index, (cell, width)) in cells.iter().zip(&widths).enumerate() {
+            line.push_str(cell);
+            if index < last {
+                let padding = width - cell.chars().count() + 2;
+                line.extend(std::iter::repeat_n(' ', padding));
+            }
+        }
+        println!("{}", line);
+    };
+
+    print_row(headers.to_vec());
+    for row in &rows {
+        print_row(row.iter().map(|cell| cell.as_str()).collect());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_args, Command};
+
+    fn parse(args: &str) -> Result<Command, String> {
+        parse_args(args.split_whitespace().map(str::to_owned)).map(|options| options.command)
+    }
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(parse("--json login"), Ok(Command::Login));
+        assert_eq!(
+            parse("stations rename 42 Late Night Jazz"),
+            Ok(Command::RenameStation {
+                station_id: "42".to_owned(),
+                name: "Late Night Jazz".to_owned(),
+            })
+        );
+        assert_eq!(
+            parse("rate 42 T1 down"),
+            Ok(Command::Rate {
+                station_id: "42".to_owned(),
+                track_token: "T1".to_owned(),
+                is_positive: false,
+            })
+        );
+        assert_eq!(
+            parse("search --json"),
+            Ok(Command::Search {
+                text: "--json".to_owned()
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_commands_before_logging_in() {
+        assert_eq!(parse("--json"), Err("missing command".to_owned()));
+        assert!(parse("stations frobnicate").is_err());
+        assert!(parse("stations rename 42").is_err());
+        assert!(parse("rate 42 T1 sideways").is_err());
+        assert!(parse("--record a --replay b login").is_err());
+    }
+}
@@ -0,0 +1,49 @@
+//! Test helpers: a fake Pandora server and logins through it.
+
+use pandora_rs2::error::Result;
+use pandora_rs2::method::Method;
+use pandora_rs2::protocol::{HttpRequest, HttpResponse};
+use pandora_rs2::{EndpointStrategy, Pandora, Transport};
+
+use serde_json::json;
+
+/// Transport answering the logins, listing one station and failing
+/// every other call with `StationDoesNotExists`.
+#[derive(Debug)]
+pub struct Server;
+
+impl Transport for Server {
+    fn execute(&self, request: &HttpRequest) -> Result<HttpResponse> {
+        let body = match request.method {
+            Method::AuthPartnerLogin => json!({
+                "stat": "ok",
+                "result": {
+                    "partnerId": "42",
+                    "partnerAuthToken": "partner-token",
+                    "syncTime": "87f9b461070d0efd5a143d430aafebf9",
+                },
+            }),
+            Method::AuthUserLogin => json!({
+                "stat": "ok",
+                "result": { "userId": "123", "userAuthToken": "user-token" },
+            }),
+            Method::UserGetStationList => json!({
+                "stat": "ok",
+                "result": {
+                    "stations": [{ "stationId": "1", "stationName": "Jazz" }],
+                    "checksum": "abc",
+                },
+            }),
+            _ => json!({ "stat": "fail", "message": "no such station", "code": 1006 }),
+        };
+        Ok(HttpResponse {
+            status: 200,
+            body: body.to_string(),
+        })
+    }
+}
+
+/// Logs in to `Server`.
+pub fn login(username: &str, password: &str) -> Result<Pandora> {
+    Pandora::with_transport(username, password, EndpointStrategy::default(), Server)
+}
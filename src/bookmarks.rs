@@ -0,0 +1,172 @@
+//! Bookmarked songs and artists.
+
+use super::error::Result;
+use super::method::Method;
+use super::playlist::ToTrackToken;
+use super::request::ApiRequest;
+use super::Pandora;
+
+use serde::de::IgnoredAny;
+
+/// Handler for the user's bookmarks.
+pub struct Bookmarks<'a> {
+    pandora: &'a Pandora,
+}
+
+impl<'a> Bookmarks<'a> {
+    pub fn new(pandora: &'a Pandora) -> Bookmarks<'a> {
+        Bookmarks { pandora }
+    }
+
+    /// Gets every song and artist bookmark.
+    pub fn list(&self) -> Result<BookmarkList> {
+        self.pandora.call(&GetBookmarksRequest {})
+    }
+
+    /// Bookmarks the song of a track.
+    pub fn add_song<T>(&self, track: T) -> Result<SongBookmark>
+    where
+        T: ToTrackToken,
+    {
+        self.pandora.call(&AddSongBookmarkRequest {
+            track_token: track.to_track_token().unwrap_or_default(),
+        })
+    }
+
+    /// Bookmarks the artist of a track.
+    pub fn add_artist<T>(&self, track: T) -> Result<ArtistBookmark>
+    where
+        T: ToTrackToken,
+    {
+        self.pandora.call(&AddArtistBookmarkRequest {
+            track_token: track.to_track_token().unwrap_or_default(),
+        })
+    }
+
+    pub fn delete_song(&self, bookmark_token: &str) -> Result<()> {
+        self.pandora
+            .call(&DeleteSongBookmarkRequest {
+                bookmark_token: bookmark_token.to_owned(),
+            })
+            .map(|_| ())
+    }
+
+    pub fn delete_artist(&self, bookmark_token: &str) -> Result<()> {
+        self.pandora
+            .call(&DeleteArtistBookmarkRequest {
+                bookmark_token: bookmark_token.to_owned(),
+            })
+            .map(|_| ())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BookmarkList {
+    #[serde(default)]
+    pub songs: Vec<SongBookmark>,
+    #[serde(default)]
+    pub artists: Vec<ArtistBookmark>,
+}
+
+/// Song bookmark.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SongBookmark {
+    #[serde(rename = "bookmarkToken")]
+    pub bookmark_token: String,
+    #[serde(rename = "musicToken")]
+    pub music_token: String,
+    #[serde(rename = "songName")]
+    pub song_name: String,
+    #[serde(rename = "artistName")]
+    pub artist_name: String,
+    #[serde(rename = "albumName")]
+    pub album_name: Option<String>,
+    #[serde(rename = "artUrl")]
+    pub art_url: Option<String>,
+    #[serde(rename = "sampleUrl")]
+    pub sample_url: Option<String>,
+}
+
+/// Artist bookmark.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArtistBookmark {
+    #[serde(rename = "bookmarkToken")]
+    pub bookmark_token: String,
+    #[serde(rename = "musicToken")]
+    pub music_token: String,
+    #[serde(rename = "artistName")]
+    pub artist_name: String,
+    #[serde(rename = "artUrl")]
+    pub art_url: Option<String>,
+}
+
+////////////////////
+// Request structs
+////////////////////
+
+#[derive(Serialize)]
+struct GetBookmarksRequest {}
+
+impl ApiRequest for GetBookmarksRequest {
+    type Response = BookmarkList;
+
+    fn method(&self) -> Method {
+        Method::UserGetBookmarks
+    }
+}
+
+#[derive(Serialize)]
+struct AddSongBookmarkRequest {
+    #[serde(rename = "trackToken")]
+    track_token: String,
+}
+
+impl ApiRequest for AddSongBookmarkRequest {
+    type Response = SongBookmark;
+
+    fn method(&self) -> Method {
+        Method::BookmarkAddSongBookmark
+    }
+}
+
+#[derive(Serialize)]
+struct AddArtistBookmarkRequest {
+    #[serde(rename = "trackToken")]
+    track_token: String,
+}
+
+impl ApiRequest for AddArtistBookmarkRequest {
+    type Response = ArtistBookmark;
+
+    fn method(&self) -> Method {
+        Method::BookmarkAddArtistBookmark
+    }
+}
+
+#[derive(Serialize)]
+struct DeleteSongBookmarkRequest {
+    #[serde(rename = "bookmarkToken")]
+    bookmark_token: String,
+}
+
+impl ApiRequest for DeleteSongBookmarkRequest {
+    type Response = IgnoredAny;
+
+    fn method(&self) -> Method {
+        Method::BookmarkDeleteSongBookmark
+    }
+}
+
+#[derive(Serialize)]
+struct DeleteArtistBookmarkRequest {
+    #[serde(rename = "bookmarkToken")]
+    bookmark_token: String,
+}
+
+impl ApiRequest for DeleteArtistBookmarkRequest {
+    type Response = IgnoredAny;
+
+    fn method(&self) -> Method {
+        Method::BookmarkDeleteArtistBookmark
+    }
+}
@@ -0,0 +1,336 @@
+//! Recording API sessions to a cassette file and replaying them offline.
+//!
+//! A cassette has one JSON interaction per line: the method, the URL and
+//! the decrypted request body, and the response, with tokens, passwords
+//! and the user's account details redacted. `Recorder` wraps a transport
+//! to write them, and `Replay` serves them back without a network:
+//!
+//! ```no_run
+//! use pandora_rs2::cassette::{Recorder, Replay, ReplayMode};
+//! use pandora_rs2::{EndpointStrategy, Pandora};
+//!
+//! # fn main() -> pandora_rs2::error::Result<()> {
+//! let recorder = Recorder::create("session.jsonl", reqwest::Client::new())?;
+//! let pandora =
+//!     Pandora::with_transport("user", "password", EndpointStrategy::default(), recorder)?;
+//! pandora.stations().list()?;
+//!
+//! let replay = Replay::open("session.jsonl", ReplayMode::InOrder)?;
+//! let pandora = Pandora::with_transport("", "", EndpointStrategy::default(), replay)?;
+//! pandora.stations().list()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use serde_json;
+use serde_json::value::Value;
+
+use super::error::{Error, Result};
+use super::protocol::{self, HttpRequest, HttpResponse};
+use super::transport::Transport;
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+/// A recorded request and its response.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Interaction {
+    pub method: String,
+    /// URL with its auth token and user ID redacted.
+    pub url: String,
+    /// Request body before encryption, with secrets redacted.
+    pub request: Value,
+    pub status: u16,
+    /// Response body with secrets redacted.
+    pub response: Value,
+}
+
+impl Interaction {
+    pub fn new(request: &HttpRequest, response: &HttpResponse) -> Self {
+        Interaction {
+            method: request.method.to_string(),
+            url: request.redacted_url().into_string(),
+            request: body_value(&request.redacted_body()),
+            status: response.status,
+            response: body_value(&protocol::redact(&response.body)),
+        }
+    }
+
+    /// Returns true if this is a recording of `request`. Sync times differ
+    /// from one session to the next, so they aren't compared.
+    pub fn matches(&self, request: &HttpRequest) -> bool {
+        self.method == request.method.to_string()
+            && without_sync_time(&self.request)
+                == without_sync_time(&body_value(&request.redacted_body()))
+    }
+
+    fn to_response(&self) -> HttpResponse {
+        let body = match self.response {
+            Value::String(ref body) => body.clone(),
+            ref body => body.to_string(),
+        };
+        HttpResponse {
+            status: self.status,
+            body,
+        }
+    }
+}
+
+/// Parses a body as JSON, keeping it as a string if it isn't.
+fn body_value(body: &str) -> Value {
+    serde_json::from_str(body).unwrap_or_else(|_| Value::String(body.to_owned()))
+}
+
+fn without_sync_time(body: &Value) -> Value {
+    let mut body = body.clone();
+    if let Some(obj) = body.as_object_mut() {
+        obj.remove("syncTime");
+    }
+    body
+}
+
+/// Transport that records every interaction of another one to a cassette.
+#[derive(Debug)]
+pub struct Recorder<X> {
+    inner: X,
+    file: Mutex<File>,
+}
+
+impl<X> Recorder<X>
+where
+    X: Transport,
+{
+    /// Records to the cassette at `path`, replacing it.
+    pub fn create<P>(path: P, inner: X) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(Recorder {
+            inner,
+            file: Mutex::new(File::create(path)?),
+        })
+    }
+
+    /// Records to the end of the cassette at `path`, creating it if needed.
+    pub fn append<P>(path: P, inner: X) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Recorder {
+            inner,
+            file: Mutex::new(file),
+        })
+    }
+}
+
+impl<X> Transport for Recorder<X>
+where
+    X: Transport,
+{
+    fn execute(&self, request: &HttpRequest) -> Result<HttpResponse> {
+        let response = self.inner.execute(request)?;
+        let mut line = serde_json::to_string(&Interaction::new(request, &response))?;
+        line.push('\n');
+
+        let mut file = self.file.lock().unwrap();
+        file.write_all(line.as_bytes())?;
+        file.flush()?;
+        Ok(response)
+    }
+}
+
+/// How `Replay` picks the interaction answering a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayMode {
+    /// Serve the interactions in the order they were recorded. A request
+    /// for another method than the next interaction's fails.
+    InOrder,
+    /// Serve the first interaction not served yet with the request's
+    /// method and body.
+    Matching,
+}
+
+/// Transport serving the responses of a cassette instead of calling the
+/// API. Requests without a recorded response fail with an I/O error of
+/// kind `NotFound`.
+#[derive(Debug)]
+pub struct Replay {
+    mode: ReplayMode,
+    /// Interactions, with `None` in place of the ones already served.
+    interactions: Mutex<Vec<Option<Interaction>>>,
+}
+
+impl Replay {
+    /// Replays the cassette at `path`.
+    pub fn open<P>(path: P, mode: ReplayMode) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let mut interactions = Vec::new();
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                interactions.push(serde_json::from_str(&line)?);
+            }
+        }
+        Ok(Replay::new(interactions, mode))
+    }
+
+    pub fn new(interactions: Vec<Interaction>, mode: ReplayMode) -> Self {
+        Replay {
+            mode,
+            interactions: Mutex::new(interactions.into_iter().map(Some).collect()),
+        }
+    }
+
+    /// Returns the number of interactions not served yet.
+    pub fn remaining(&self) -> usize {
+        let interactions = self.interactions.lock().unwrap();
+        interactions.iter().filter(|i| i.is_some()).count()
+    }
+}
+
+impl Transport for Replay {
+    fn execute(&self, request: &HttpRequest) -> Result<HttpResponse> {
+        let mut interactions = self.interactions.lock().unwrap();
+        let slot = match self.mode {
+            ReplayMode::InOrder => interactions
+                .iter_mut()
+                .find(|i| i.is_some())
+                .filter(|i| i.as_ref().unwrap().method == request.method.to_string()),
+            ReplayMode::Matching => interactions
+                .iter_mut()
+                .find(|i| i.as_ref().is_some_and(|i| i.matches(request))),
+        };
+
+        match slot.and_then(Option::take) {
+            Some(interaction) => Ok(interaction.to_response()),
+            None => Err(Error::Io(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no recorded response for {}", request.method),
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Interaction, Recorder, Replay, ReplayMode};
+    use crate::method::Method;
+    use crate::protocol::{encode, HttpResponse};
+    use crate::testing::{partner_login, Script};
+    use crate::transport::Transport;
+    use crate::{EndpointStrategy, Pandora, DEFAULT_ENDPOINT};
+
+    use serde_json::json;
+
+    use std::env;
+    use std::fs;
+    use std::process;
+    use std::sync::Arc;
+
+    fn interaction(method: Method, body: serde_json::Value, response: &str) -> Interaction {
+        let request = encode(DEFAULT_ENDPOINT, method, false, Some(body), None).unwrap();
+        let response = HttpResponse {
+            status: 200,
+            body: response.to_owned(),
+        };
+        Interaction::new(&request, &response)
+    }
+
+    #[test]
+    fn records_without_secrets() {
+        let interaction = interaction(
+            Method::AuthUserLogin,
+            json!({ "username": "me", "password": "hunter2" }),
+            r#"{"stat":"ok","result":{"userAuthToken":"abc"}}"#,
+        );
+        assert_eq!(interaction.request["password"], "<redacted>");
+        assert_eq!(interaction.request["username"], "<redacted>");
+        assert_eq!(
+            interaction.response["result"]["userAuthToken"],
+            "<redacted>"
+        );
+    }
+
+    #[test]
+    fn replays_in_order_or_by_matching() {
+        let first = interaction(Method::StationGetStation, json!({ "id": 1 }), "1");
+        let second = interaction(Method::StationGetStation, json!({ "id": 2 }), "2");
+        let request = |id| {
+            encode(
+                DEFAULT_ENDPOINT,
+                Method::StationGetStation,
+                false,
+                Some(json!({ "id": id, "syncTime": 42 })),
+                None,
+            )
+            .unwrap()
+        };
+
+        let replay = Replay::new(vec![first.clone(), second.clone()], ReplayMode::InOrder);
+        assert_eq!(replay.execute(&request(2)).unwrap().body, "1");
+        let other = encode(DEFAULT_ENDPOINT, Method::MusicSearch, false, None, None).unwrap();
+        assert!(replay.execute(&other).is_err());
+        assert_eq!(replay.execute(&request(1)).unwrap().body, "2");
+        assert_eq!(replay.remaining(), 0);
+
+        let replay = Replay::new(vec![first, second], ReplayMode::Matching);
+        assert_eq!(replay.execute(&request(2)).unwrap().body, "2");
+        assert_eq!(replay.execute(&request(1)).unwrap().body, "1");
+        assert!(replay.execute(&request(1)).is_err());
+    }
+
+    #[test]
+    fn replays_a_recorded_session() {
+        let path = env::temp_dir().join(format!("pandora-cassette-{}.jsonl", process::id()));
+        let strategy = EndpointStrategy::default;
+
+        let script = Script::new(vec![
+            partner_login(),
+            json!({
+                "stat": "ok",
+                "result": {
+                    "userId": "123",
+                    "userAuthToken": "user-token",
+                    "username": "me@example.com",
+                    "listenerId": "456",
+                },
+            }),
+            json!({
+                "stat": "ok",
+                "result": {
+                    "stations": [{ "stationId": "1", "stationName": "Jazz" }],
+                    "checksum": "abc",
+                },
+            }),
+        ]);
+        let recorder = Recorder::create(&path, Arc::clone(&script)).unwrap();
+        let pandora =
+            Pandora::with_transport("me@example.com", "hunter2", strategy(), recorder).unwrap();
+        let recorded = pandora.stations().list().unwrap();
+
+        let cassette = fs::read_to_string(&path).unwrap();
+        assert_eq!(cassette.lines().count(), 3);
+        for secret in &[
+            "me@example.com",
+            "hunter2",
+            "partner-token",
+            "user-token",
+            "456",
+        ] {
+            assert!(!cassette.contains(secret), "{} was recorded", secret);
+        }
+
+        let replay = Replay::open(&path, ReplayMode::InOrder).unwrap();
+        fs::remove_file(&path).unwrap();
+        let pandora = Pandora::with_transport("", "", strategy(), replay).unwrap();
+        let replayed = pandora.stations().list().unwrap();
+        assert_eq!(replayed.len(), 1);
+        assert_eq!(replayed[0].station_id, recorded[0].station_id);
+        assert_eq!(replayed[0].station_name, "Jazz");
+    }
+}
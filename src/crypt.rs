@@ -1,88 +1,199 @@
 //! Encryption and Decryption using Blowfish with ECB mode.
 
-use std::ffi::OsString;
+use std::error::Error as StdError;
+use std::fmt;
+use std::str::Utf8Error;
 
 use crypto::blowfish::Blowfish;
 use crypto::symmetriccipher::{BlockDecryptor, BlockEncryptor};
 
+/// Byte used to pad the last block; inputs that end with it
+/// don't survive a round trip.
 const PADDING_BYTE: u8 = 2;
 
+const BLOCK_SIZE: usize = 8;
+
+const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+
 /// Returns the encrypted input using the given key.
 ///
 /// The returned string is encoded in hexadecimal notation,
 /// which is a UTF-8 string, so it's fine to return it using
 /// the `String` type.
-pub fn encrypt(key: &str, input: &str) -> String {
-    let cipherbytes = cipher_with(
-        key.as_bytes(),
-        input.as_bytes(),
-        |blowfish, from, mut to| {
-            blowfish.encrypt_block(from, to);
-        },
-    );
-
-    // Generate hexadecimal representation of `cipherbytes`.
-    let mut output = String::with_capacity(cipherbytes.len() * 2);
-    for b in cipherbytes {
-        output.push_str(&format!("{:02x}", b));
-    }
-    output
+pub fn encrypt<T>(key: &str, input: T) -> String
+where
+    T: AsRef<[u8]>,
+{
+    encrypt_with(&Blowfish::new(key.as_bytes()), input.as_ref())
 }
 
 /// Returns the decrypted input using the given key.
 ///
-/// Because Strings must be UTF-8 compilant, and decrypting
-/// doesn't guarantees an UTF-8 string, we return
-/// a OsString which doesn't have to be UTF-8 compilant.
-pub fn decrypt(key: &str, hex_input: &str) -> Vec<u8> {
-    use std::str;
-    use std::u8;
+/// Decrypting doesn't guarantee a UTF-8 string, so the raw
+/// bytes are returned; see `decrypt_to_string`.
+pub fn decrypt(key: &str, hex_input: &str) -> Result<Vec<u8>, CryptError> {
+    decrypt_with(&Blowfish::new(key.as_bytes()), hex_input)
+}
 
-    // Gets bytes from hexadecimal representation.
-    let mut input = Vec::with_capacity(hex_input.len());
-    for chunk in hex_input.as_bytes().chunks(2) {
-        // `chunk` is utf-8 since it is comming from &str.
-        let fragment = unsafe { str::from_utf8_unchecked(chunk) };
-        let byte = u8::from_str_radix(fragment, 16).unwrap_or(0);
-        input.push(byte);
+/// Returns the decrypted input using the given key, failing
+/// if it isn't valid UTF-8.
+pub fn decrypt_to_string(key: &str, hex_input: &str) -> Result<String, CryptError> {
+    into_string(decrypt(key, hex_input)?)
+}
+
+/// Encryption and decryption with a partner's keys.
+///
+/// The Blowfish key schedules are expanded once, which is much more
+/// expensive than ciphering the small payloads the API uses, so this
+/// should be kept around instead of calling `encrypt` and `decrypt`.
+#[derive(Clone)]
+pub struct PandoraCipher {
+    encryptor: Blowfish,
+    decryptor: Blowfish,
+}
+
+impl PandoraCipher {
+    pub fn new(encrypt_key: &str, decrypt_key: &str) -> Self {
+        PandoraCipher {
+            encryptor: Blowfish::new(encrypt_key.as_bytes()),
+            decryptor: Blowfish::new(decrypt_key.as_bytes()),
+        }
     }
 
-    let mut cipherbytes = cipher_with(key.as_bytes(), &input, |blowfish, from, mut to| {
-        blowfish.decrypt_block(from, to);
-    });
+    /// Returns the input encrypted with the encryption key, in
+    /// hexadecimal notation.
+    pub fn encrypt<T>(&self, input: T) -> String
+    where
+        T: AsRef<[u8]>,
+    {
+        encrypt_with(&self.encryptor, input.as_ref())
+    }
 
-    // Ignore up to `PADDING_BYTE`.
-    if let Some(index) = cipherbytes.iter().position(|&b| b == PADDING_BYTE) {
-        cipherbytes.truncate(index);
+    /// Returns the hexadecimal input decrypted with the decryption key.
+    pub fn decrypt(&self, hex_input: &str) -> Result<Vec<u8>, CryptError> {
+        decrypt_with(&self.decryptor, hex_input)
     }
 
-    cipherbytes
+    /// Like `decrypt`, failing if the result isn't valid UTF-8.
+    pub fn decrypt_to_string(&self, hex_input: &str) -> Result<String, CryptError> {
+        into_string(self.decrypt(hex_input)?)
+    }
 }
 
-/// Divides the input in blocks and ciphers it using the given closure.
-fn cipher_with<F>(key: &[u8], input: &[u8], func: F) -> Vec<u8>
-where
-    F: Fn(&Blowfish, &[u8], &mut [u8]),
-{
-    let blowfish = Blowfish::new(key);
-    let block_size = <Blowfish as BlockEncryptor>::block_size(&blowfish);
+impl fmt::Debug for PandoraCipher {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("PandoraCipher { .. }")
+    }
+}
 
-    // Input and output bytes
-    let input_len = round_len(input.len(), block_size);
-    let mut input = input.to_vec();
-    input.resize(input_len, PADDING_BYTE);
+/// Encrypts the input block by block, padding the last one, and
+/// writes the hexadecimal representation of the result.
+fn encrypt_with(blowfish: &Blowfish, input: &[u8]) -> String {
+    let mut output = String::with_capacity(round_len(input.len(), BLOCK_SIZE) * 2);
+    let mut plain = [0; BLOCK_SIZE];
+    let mut cipher = [0; BLOCK_SIZE];
 
-    let mut output: Vec<u8> = Vec::with_capacity(input_len);
-    unsafe {
-        output.set_len(input_len);
+    for chunk in input.chunks(BLOCK_SIZE) {
+        plain[..chunk.len()].copy_from_slice(chunk);
+        for byte in &mut plain[chunk.len()..] {
+            *byte = PADDING_BYTE;
+        }
+        blowfish.encrypt_block(&plain, &mut cipher);
+
+        for &byte in &cipher {
+            output.push(char::from(HEX_DIGITS[usize::from(byte >> 4)]));
+            output.push(char::from(HEX_DIGITS[usize::from(byte & 0x0f)]));
+        }
+    }
+    output
+}
+
+/// Decodes the hexadecimal input block by block and decrypts it,
+/// stripping the padding.
+fn decrypt_with(blowfish: &Blowfish, hex_input: &str) -> Result<Vec<u8>, CryptError> {
+    let hex_input = hex_input.as_bytes();
+    if !hex_input.len().is_multiple_of(BLOCK_SIZE * 2) {
+        return Err(CryptError::InvalidLength {
+            length: hex_input.len(),
+        });
     }
 
-    // Encrypts input and into output
-    for (ichunk, mut ochunk) in input.chunks(block_size).zip(output.chunks_mut(block_size)) {
-        func(&blowfish, ichunk, ochunk);
+    let mut output = Vec::with_capacity(hex_input.len() / 2);
+    let mut cipher = [0; BLOCK_SIZE];
+    let mut plain = [0; BLOCK_SIZE];
+
+    for (block, hex_block) in hex_input.chunks(BLOCK_SIZE * 2).enumerate() {
+        for (index, byte) in cipher.iter_mut().enumerate() {
+            let position = block * BLOCK_SIZE * 2 + index * 2;
+            let high =
+                hex_value(hex_block[index * 2]).ok_or(CryptError::InvalidHex { position })?;
+            let low = hex_value(hex_block[index * 2 + 1]).ok_or(CryptError::InvalidHex {
+                position: position + 1,
+            })?;
+            *byte = high << 4 | low;
+        }
+        blowfish.decrypt_block(&cipher, &mut plain);
+        output.extend_from_slice(&plain);
     }
 
-    output
+    // Strip the trailing `PADDING_BYTE`s.
+    let len = output
+        .iter()
+        .rposition(|&b| b != PADDING_BYTE)
+        .map_or(0, |index| index + 1);
+    output.truncate(len);
+
+    Ok(output)
+}
+
+fn into_string(bytes: Vec<u8>) -> Result<String, CryptError> {
+    String::from_utf8(bytes).map_err(|e| CryptError::InvalidUtf8(e.utf8_error()))
+}
+
+/// Errors produced when decrypting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CryptError {
+    /// The character at `position` isn't a hexadecimal digit.
+    InvalidHex { position: usize },
+    /// The input doesn't decode into whole Blowfish blocks.
+    InvalidLength { length: usize },
+    /// The decrypted bytes aren't valid UTF-8.
+    InvalidUtf8(Utf8Error),
+}
+
+impl fmt::Display for CryptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CryptError::InvalidHex { position } => {
+                write!(f, "invalid hexadecimal digit at position {}", position)
+            }
+            CryptError::InvalidLength { length } => write!(
+                f,
+                "invalid input length {}, expected a multiple of 16 hex digits",
+                length
+            ),
+            CryptError::InvalidUtf8(ref e) => write!(f, "decrypted data is not UTF-8: {}", e),
+        }
+    }
+}
+
+impl StdError for CryptError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match *self {
+            CryptError::InvalidUtf8(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the value of an ASCII hexadecimal digit.
+fn hex_value(digit: u8) -> Option<u8> {
+    match digit {
+        b'0'..=b'9' => Some(digit - b'0'),
+        b'a'..=b'f' => Some(digit - b'a' + 10),
+        b'A'..=b'F' => Some(digit - b'A' + 10),
+        _ => None,
+    }
 }
 
 /// Rounds the given len so that it contains blocks
@@ -98,8 +209,8 @@ fn round_len(len: usize, block_size: usize) -> usize {
 
 #[cfg(test)]
 mod tests {
-    use super::{decrypt, encrypt};
-    use std::ffi::OsString;
+    use super::{decrypt, decrypt_to_string, encrypt, CryptError, PandoraCipher, PADDING_BYTE};
+    use quickcheck::{quickcheck, Arbitrary, Gen};
 
     struct Test {
         key: String,
@@ -122,4 +233,86 @@ mod tests {
             assert_eq!(test.cipher_text, cipher_text);
         }
     }
+
+    #[test]
+    fn decrypt_test_vector() {
+        for test in get_test_vector() {
+            let plain_text = decrypt_to_string(&test.key, &test.cipher_text).unwrap();
+            assert_eq!(test.plain_text, plain_text);
+        }
+    }
+
+    #[test]
+    fn cipher_matches_test_vector() {
+        for test in get_test_vector() {
+            let cipher = PandoraCipher::new(&test.key, &test.key);
+            assert_eq!(test.cipher_text, cipher.encrypt(&test.plain_text));
+            assert_eq!(
+                test.plain_text,
+                cipher.decrypt_to_string(&test.cipher_text).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn decrypt_rejects_malformed_input() {
+        let key = "R=U!LH$O2B#";
+        assert_eq!(
+            decrypt(key, "4a6b45612b01861"),
+            Err(CryptError::InvalidLength { length: 15 })
+        );
+        assert_eq!(
+            decrypt(key, "4a6b45612b0186zz"),
+            Err(CryptError::InvalidHex { position: 14 })
+        );
+    }
+
+    #[test]
+    fn round_trip_keeps_inner_padding_bytes() {
+        let key = "6#26FRL$ZWD";
+        let input = [0x41, PADDING_BYTE, 0x42, PADDING_BYTE, 0xff, 0x00, 0x43];
+        assert_eq!(decrypt(key, &encrypt(key, input)).unwrap(), input);
+
+        let input = "a\u{2}b";
+        assert_eq!(decrypt_to_string(key, &encrypt(key, input)).unwrap(), input);
+    }
+
+    #[test]
+    fn round_trip_block_aligned() {
+        let key = "6#26FRL$ZWD";
+        for input in &["", "12345678", "0123456789abcdef"] {
+            let cipher_text = encrypt(key, input);
+            assert_eq!(cipher_text.len(), input.len() * 2);
+            assert_eq!(&decrypt_to_string(key, &cipher_text).unwrap(), input);
+        }
+    }
+
+    /// Blowfish key between 4 and 56 bytes.
+    #[derive(Debug, Clone)]
+    struct Key(String);
+
+    impl Arbitrary for Key {
+        fn arbitrary(g: &mut Gen) -> Key {
+            let len = 4 + usize::arbitrary(g) % 53;
+            let key = (0..len)
+                .map(|_| char::from(b' ' + u8::arbitrary(g) % 95))
+                .collect();
+            Key(key)
+        }
+    }
+
+    quickcheck! {
+        fn round_trip_bytes(key: Key, input: Vec<u8>) -> bool {
+            let mut input = input;
+            while input.last() == Some(&PADDING_BYTE) {
+                input.pop();
+            }
+            decrypt(&key.0, &encrypt(&key.0, &input)).unwrap() == input
+        }
+
+        fn round_trip_strings(key: Key, input: String) -> bool {
+            let input = input.trim_end_matches(char::from(PADDING_BYTE));
+            decrypt_to_string(&key.0, &encrypt(&key.0, input)).unwrap() == input
+        }
+    }
 }
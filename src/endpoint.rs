@@ -0,0 +1,278 @@
+//! Choice of the tuner endpoint each request is sent to.
+
+use super::error::{ApiErrorCode, Error, Result};
+use super::{Endpoint, DEFAULT_ENDPOINT, ENDPOINTS};
+
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// How requests pick among tuner endpoints.
+///
+/// Either way, a request answered with `SecureProtocolRequired` or
+/// `WrongProtocol` is retried on the HTTPS version of its endpoint, which
+/// fails over among the HTTPS versions of the strategy's endpoints.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EndpointStrategy {
+    /// Always use this endpoint.
+    Fixed(Endpoint<'static>),
+    /// Start with the first endpoint and move on to the next one when it
+    /// can't be connected to, sticking with the first one that answers.
+    Failover(Vec<Endpoint<'static>>),
+}
+
+impl Default for EndpointStrategy {
+    /// Fails over across `ENDPOINTS`, HTTPS first, starting with
+    /// `DEFAULT_ENDPOINT`.
+    fn default() -> Self {
+        let https = ENDPOINTS.iter().filter(|e| e.is_https());
+        let http = ENDPOINTS.iter().filter(|e| !e.is_https());
+
+        let mut endpoints = vec![DEFAULT_ENDPOINT];
+        for &endpoint in https.chain(http) {
+            if !endpoints.contains(&endpoint) {
+                endpoints.push(endpoint);
+            }
+        }
+        EndpointStrategy::Failover(endpoints)
+    }
+}
+
+/// Endpoints of a `Pandora`, remembering the last one that answered.
+#[derive(Debug)]
+pub(crate) struct Endpoints {
+    /// The strategy's endpoints, followed by the HTTPS versions of its
+    /// HTTP endpoints that aren't already listed. Each part is a separate
+    /// failover ring.
+    list: Vec<Endpoint<'static>>,
+    /// Number of endpoints the strategy itself lists.
+    failover_len: usize,
+    current: AtomicUsize,
+}
+
+impl Endpoints {
+    pub fn new(strategy: EndpointStrategy) -> Self {
+        let mut list = match strategy {
+            EndpointStrategy::Fixed(endpoint) => vec![endpoint],
+            EndpointStrategy::Failover(endpoints) => endpoints,
+        };
+        if list.is_empty() {
+            list.push(DEFAULT_ENDPOINT);
+        }
+        let failover_len = list.len();
+
+        for index in 0..failover_len {
+            if let Some(https) = list[index].to_https() {
+                if !list.contains(&https) {
+                    list.push(https);
+                }
+            }
+        }
+
+        Endpoints {
+            list,
+            failover_len,
+            current: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the endpoint requests are currently sent to.
+    pub fn current(&self) -> Endpoint<'static> {
+        self.list[self.current.load(Ordering::Relaxed)]
+    }
+
+    /// Runs `f` against the current endpoint. Failed connections move on
+    /// to the next endpoint until each has been tried, and protocol errors
+    /// switch to HTTPS. The endpoint that answered is used from then on.
+    ///
+    /// Only failures to connect fail over: after any other error the
+    /// server may have acted on the request, and sending it again could
+    /// create a station or seed twice.
+    pub fn run<T, F>(&self, mut f: F) -> Result<T>
+    where
+        F: FnMut(Endpoint<'static>) -> Result<T>,
+    {
+        let start = self.current.load(Ordering::Relaxed);
+        let mut index = start;
+        let mut tried = 0;
+        let mut upgraded = false;
+
+        loop {
+            let endpoint = self.list[index];
+            let result = f(endpoint);
+            let next = match result {
+                Err(ref err) if err.is_connection_error() => {
+                    let ring = self.ring(index);
+                    tried += 1;
+                    if tried < ring.len() {
+                        Some(ring.start + (index - ring.start + 1) % ring.len())
+                    } else {
+                        None
+                    }
+                }
+                Err(ref err) if needs_https(err) && !upgraded => {
+                    upgraded = true;
+                    tried = 0;
+                    endpoint
+                        .to_https()
+                        .and_then(|https| self.list.iter().position(|&e| e == https))
+                }
+                _ => None,
+            };
+
+            match next {
+                Some(next) => index = next,
+                None => {
+                    if result.is_ok() && index != start {
+                        self.current.store(index, Ordering::Relaxed);
+                    }
+                    return result;
+                }
+            }
+        }
+    }
+
+    /// Returns the endpoints `index` fails over among: the strategy's own,
+    /// or the HTTPS ones added for upgrades.
+    fn ring(&self, index: usize) -> Range<usize> {
+        if index < self.failover_len {
+            0..self.failover_len
+        } else {
+            self.failover_len..self.list.len()
+        }
+    }
+}
+
+fn needs_https(err: &Error) -> bool {
+    matches!(
+        err.api_code(),
+        Some(&ApiErrorCode::SecureProtocolRequired) | Some(&ApiErrorCode::WrongProtocol)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EndpointStrategy, Endpoints};
+    use crate::error::{ApiErrorCode, Error, RequestContext, Result};
+    use crate::method::Method;
+    use crate::{Endpoint, ENDPOINTS};
+
+    use reqwest::Client;
+
+    use std::io;
+    use std::net::TcpListener;
+
+    /// Returns the error of a request to a local port nothing listens on.
+    fn connection_error() -> Error {
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let url = format!("http://{}/", addr);
+        Error::from(Client::new().post(&url).send().unwrap_err())
+    }
+
+    fn api_error(code: ApiErrorCode) -> Error {
+        Error::Api {
+            message: String::new(),
+            code,
+            context: Box::new(RequestContext::new(Method::UserGetStationList, "", 200, "")),
+        }
+    }
+
+    #[test]
+    fn default_tries_https_first() {
+        let EndpointStrategy::Failover(endpoints) = EndpointStrategy::default() else {
+            panic!("default strategy should fail over");
+        };
+        assert_eq!(
+            endpoints,
+            [ENDPOINTS[1], ENDPOINTS[3], ENDPOINTS[0], ENDPOINTS[2]]
+        );
+    }
+
+    #[test]
+    fn fails_over_and_remembers_the_healthy_endpoint() {
+        let endpoints =
+            Endpoints::new(EndpointStrategy::Failover(vec![ENDPOINTS[1], ENDPOINTS[3]]));
+        let mut calls = Vec::new();
+        let result: Result<Endpoint> = endpoints.run(|endpoint| {
+            calls.push(endpoint);
+            if endpoint == ENDPOINTS[1] {
+                Err(connection_error())
+            } else {
+                Ok(endpoint)
+            }
+        });
+        assert_eq!(result.unwrap(), ENDPOINTS[3]);
+        assert_eq!(calls, [ENDPOINTS[1], ENDPOINTS[3]]);
+        assert_eq!(endpoints.current(), ENDPOINTS[3]);
+
+        // Every endpoint failing returns the last error.
+        let mut calls = 0;
+        let result: Result<()> = endpoints.run(|_| {
+            calls += 1;
+            Err(connection_error())
+        });
+        assert!(result.is_err());
+        assert_eq!(calls, 2);
+    }
+
+    #[test]
+    fn upgrades_to_https() {
+        let endpoints = Endpoints::new(EndpointStrategy::Fixed(ENDPOINTS[0]));
+        let result: Result<Endpoint> = endpoints.run(|endpoint| {
+            if endpoint.is_https() {
+                Ok(endpoint)
+            } else {
+                Err(api_error(ApiErrorCode::SecureProtocolRequired))
+            }
+        });
+        assert_eq!(result.unwrap(), ENDPOINTS[1]);
+        assert_eq!(endpoints.current(), ENDPOINTS[1]);
+
+        // A fixed endpoint doesn't fail over.
+        let mut calls = 0;
+        let result: Result<()> = endpoints.run(|_| {
+            calls += 1;
+            Err(connection_error())
+        });
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn only_fails_over_when_the_request_was_not_sent() {
+        let endpoints = Endpoints::new(EndpointStrategy::default());
+        let mut calls = 0;
+        let result: Result<()> = endpoints.run(|_| {
+            calls += 1;
+            Err(Error::Io(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "timed out",
+            )))
+        });
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+        assert_eq!(endpoints.current(), ENDPOINTS[1]);
+    }
+
+    #[test]
+    fn upgraded_endpoints_fail_over() {
+        let endpoints =
+            Endpoints::new(EndpointStrategy::Failover(vec![ENDPOINTS[0], ENDPOINTS[2]]));
+        let mut calls = Vec::new();
+        let result: Result<Endpoint> = endpoints.run(|endpoint| {
+            calls.push(endpoint);
+            if !endpoint.is_https() {
+                Err(api_error(ApiErrorCode::WrongProtocol))
+            } else if endpoint == ENDPOINTS[1] {
+                Err(connection_error())
+            } else {
+                Ok(endpoint)
+            }
+        });
+        assert_eq!(result.unwrap(), ENDPOINTS[3]);
+        assert_eq!(calls, [ENDPOINTS[0], ENDPOINTS[1], ENDPOINTS[3]]);
+        assert_eq!(endpoints.current(), ENDPOINTS[3]);
+    }
+}
@@ -1,10 +1,18 @@
 use std::error::Error as StdError;
+use std::fmt;
 use std::io::Error as IoError;
+use std::time::Duration;
 
 use reqwest::Error as HttpError;
 
 use serde_json::error::Error as CodecError;
 
+use super::crypt::CryptError;
+use super::method::Method;
+
+/// Maximum number of characters of a raw response body kept in errors.
+const MAX_BODY_LEN: usize = 512;
+
 pub type Result<T> = ::std::result::Result<T, Error>;
 
 #[derive(Debug)]
@@ -12,32 +20,125 @@ pub enum Error {
     Io(IoError),
     Codec(CodecError),
     Http(HttpError),
-    Api { message: String, code: ApiErrorCode },
+    Crypt(CryptError),
+    /// The `syncTime` returned by `auth.partnerLogin` isn't a timestamp.
+    InvalidSyncTime(String),
+    /// Pandora answered with `stat: fail`.
+    Api {
+        message: String,
+        code: ApiErrorCode,
+        context: Box<RequestContext>,
+    },
+    /// Pandora answered with `stat: ok` but without a result.
+    MissingResult {
+        context: Box<RequestContext>,
+    },
+    /// The response couldn't be decoded.
+    Decode {
+        source: CodecError,
+        context: Box<RequestContext>,
+    },
+    SkipLimitReached {
+        retry_after: Duration,
+    },
 }
 
-impl StdError for Error {
-    fn description(&self) -> &str {
+impl Error {
+    /// Returns the request that caused the error, if any.
+    pub fn context(&self) -> Option<&RequestContext> {
+        match *self {
+            Error::Api { ref context, .. }
+            | Error::MissingResult { ref context }
+            | Error::Decode { ref context, .. } => Some(context),
+            _ => None,
+        }
+    }
+
+    /// Returns the API error code, if Pandora rejected the request.
+    pub fn api_code(&self) -> Option<&ApiErrorCode> {
         match *self {
-            Error::Io(ref e) => e.description(),
-            Error::Codec(ref e) => e.description(),
-            Error::Http(ref e) => e.description(),
-            Error::Api { ref message, .. } => message.as_str(),
+            Error::Api { ref code, .. } => Some(code),
+            _ => None,
         }
     }
 
-    fn cause(&self) -> Option<&StdError> {
+    /// Returns true if retrying the same request later may succeed.
+    pub fn is_retryable(&self) -> bool {
+        match *self {
+            Error::Io(_) => true,
+            Error::Http(ref e) => e.is_timeout() || e.is_server_error(),
+            Error::Api { ref code, .. } => code.is_retryable(),
+            _ => false,
+        }
+    }
+
+    /// Returns true if no connection to the server could be made, so the
+    /// request was never sent. Other failures, like timeouts or dropped
+    /// connections, may happen after the server acted on the request.
+    pub fn is_connection_error(&self) -> bool {
+        match *self {
+            Error::Http(ref e) => e
+                .get_ref()
+                .and_then(|e| e.downcast_ref::<hyper::Error>())
+                .is_some_and(hyper::Error::is_connect),
+            _ => false,
+        }
+    }
+
+    /// Returns true if the error is caused by missing or stale credentials.
+    pub fn is_auth_error(&self) -> bool {
+        match *self {
+            Error::Api { ref code, .. } => code.is_auth_error(),
+            _ => false,
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
         match *self {
             Error::Io(ref e) => Some(e),
             Error::Codec(ref e) => Some(e),
             Error::Http(ref e) => Some(e),
+            Error::Crypt(ref e) => Some(e),
+            Error::Decode { ref source, .. } => Some(source),
             _ => None,
         }
     }
 }
 
-impl ::std::fmt::Display for Error {
-    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
-        write!(f, "{:?}", self)
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref e) => write!(f, "I/O error: {}", e),
+            Error::Codec(ref e) => write!(f, "JSON error: {}", e),
+            Error::Http(ref e) => write!(f, "HTTP error: {}", e),
+            Error::Crypt(ref e) => write!(f, "decryption error: {}", e),
+            Error::InvalidSyncTime(ref sync_time) => {
+                write!(f, "invalid sync time {:?}", sync_time)
+            }
+            Error::Api {
+                ref message,
+                ref code,
+                ref context,
+            } => write!(f, "{} failed: {} ({})", context.method, code, message),
+            Error::MissingResult { ref context } => {
+                write!(f, "{} returned no result", context.method)
+            }
+            Error::Decode {
+                ref source,
+                ref context,
+            } => write!(
+                f,
+                "could not decode {} response: {}",
+                context.method, source
+            ),
+            Error::SkipLimitReached { retry_after } => write!(
+                f,
+                "skip limit reached, next skip in {}s",
+                retry_after.as_secs()
+            ),
+        }
     }
 }
 
@@ -53,15 +154,49 @@ impl From<CodecError> for Error {
     }
 }
 
+impl From<CryptError> for Error {
+    fn from(error: CryptError) -> Error {
+        Error::Crypt(error)
+    }
+}
+
 impl From<HttpError> for Error {
     fn from(error: HttpError) -> Error {
         Error::Http(error)
     }
 }
 
-#[derive(Debug)]
+/// The request an error came from.
+#[derive(Debug, Clone)]
+pub struct RequestContext {
+    pub method: Method,
+    pub endpoint: String,
+    /// HTTP status of the response.
+    pub status: u16,
+    /// Raw response body, truncated to a few hundred characters.
+    pub body: String,
+}
+
+impl RequestContext {
+    pub fn new(method: Method, endpoint: &str, status: u16, body: &str) -> Self {
+        let body = match body.char_indices().nth(MAX_BODY_LEN) {
+            Some((index, _)) => format!("{}...", &body[..index]),
+            None => body.to_owned(),
+        };
+        RequestContext {
+            method,
+            endpoint: endpoint.to_owned(),
+            status,
+            body,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ApiErrorCode {
-    Unknown,
+    /// A code this crate doesn't know about, or `None` for a failure
+    /// without a code.
+    Unknown(Option<u32>),
 
     InternalError,
     MaintenanceMode,
@@ -116,6 +251,7 @@ pub enum ApiErrorCode {
     BirthYearTooYoung,
     InvalidCountryCode,
     InvalidGender,
+    CannotRemoveAllSeeds,
     DeviceDisabled,
 
     DailyTrialLimitReached,
@@ -166,16 +302,310 @@ impl From<u32> for ApiErrorCode {
             1024 => ApiErrorCode::ZipCodeInvalid,
             1025 => ApiErrorCode::BirthYearInvalid,
             1026 => ApiErrorCode::BirthYearTooYoung,
+            // The API documentation lists 1027 for both invalid country code
+            // and invalid gender; `from_response` tells them apart by message.
             1027 => ApiErrorCode::InvalidCountryCode,
-            // TODO: Maybe not 1028, the code was 1027 (Repeated).
-            1028 => ApiErrorCode::InvalidGender,
+            1032 => ApiErrorCode::CannotRemoveAllSeeds,
             1034 => ApiErrorCode::DeviceDisabled,
             1035 => ApiErrorCode::DailyTrialLimitReached,
             1036 => ApiErrorCode::InvalidSponsor,
             1037 => ApiErrorCode::UserAlreadyUserTrial,
             1039 => ApiErrorCode::PlaylistExceeded,
 
-            _ => ApiErrorCode::Unknown,
+            code => ApiErrorCode::Unknown(Some(code)),
         }
     }
 }
+
+impl From<ApiErrorCode> for Option<u32> {
+    fn from(code: ApiErrorCode) -> Option<u32> {
+        code.code()
+    }
+}
+
+impl ApiErrorCode {
+    /// Maps the code and message of a failed response.
+    pub fn from_response(code: Option<u32>, message: &str) -> Self {
+        match code {
+            Some(1027) if message.to_lowercase().contains("gender") => ApiErrorCode::InvalidGender,
+            Some(code) => ApiErrorCode::from(code),
+            None => ApiErrorCode::Unknown(None),
+        }
+    }
+
+    /// Returns the numeric code as sent by Pandora, or `None` if the
+    /// failure came without one.
+    pub fn code(&self) -> Option<u32> {
+        let code = match *self {
+            ApiErrorCode::Unknown(code) => return code,
+
+            ApiErrorCode::InternalError => 0,
+            ApiErrorCode::MaintenanceMode => 1,
+            ApiErrorCode::UrlParamMissingMethod => 2,
+            ApiErrorCode::UrlParamMissingAuthToken => 3,
+            ApiErrorCode::UrlParamMissingPartnerId => 4,
+            ApiErrorCode::UrlParamMissingUserId => 5,
+            ApiErrorCode::SecureProtocolRequired => 6,
+            ApiErrorCode::CertifiateRequired => 7,
+            ApiErrorCode::ParameterTypeMismatch => 8,
+            ApiErrorCode::ParameterMissing => 9,
+            ApiErrorCode::ParameterValueInvalid => 10,
+            ApiErrorCode::ApiVersionNotSupported => 11,
+            ApiErrorCode::LicensingRestrictions => 12,
+            ApiErrorCode::InsufficientConnectivity => 13,
+            ApiErrorCode::UnknownMethodName => 14,
+            ApiErrorCode::WrongProtocol => 15,
+            ApiErrorCode::ReadOnlyMode => 1000,
+            ApiErrorCode::InvalidAuthToken => 1001,
+            ApiErrorCode::InvalidPartnerOrUserLogin => 1002,
+            ApiErrorCode::ListenerNotAuthorized => 1003,
+            ApiErrorCode::UserNotAuthorized => 1004,
+            ApiErrorCode::MaxStationsReached => 1005,
+            ApiErrorCode::StationDoesNotExists => 1006,
+            ApiErrorCode::ComplimentaryPeriodAlreadyInUse => 1007,
+            ApiErrorCode::CallNotAllowed => 1008,
+            ApiErrorCode::DeviceNotFound => 1009,
+            ApiErrorCode::PartnerNotAuthroized => 1010,
+            ApiErrorCode::InvalidUsername => 1011,
+            ApiErrorCode::InvalidPassword => 1012,
+            ApiErrorCode::UsernameAlreadyExists => 1013,
+            ApiErrorCode::DeviceAlreadyAssociatedToAccount => 1014,
+            ApiErrorCode::UpgradeDeviceModelInvalid => 1015,
+            ApiErrorCode::ExplicitPinIncorrect => 1018,
+            ApiErrorCode::ExplicitPinMalformed => 1020,
+            ApiErrorCode::DeviceModelInvalid => 1023,
+            ApiErrorCode::ZipCodeInvalid => 1024,
+            ApiErrorCode::BirthYearInvalid => 1025,
+            ApiErrorCode::BirthYearTooYoung => 1026,
+            ApiErrorCode::InvalidCountryCode => 1027,
+            ApiErrorCode::InvalidGender => 1027,
+            ApiErrorCode::CannotRemoveAllSeeds => 1032,
+            ApiErrorCode::DeviceDisabled => 1034,
+            ApiErrorCode::DailyTrialLimitReached => 1035,
+            ApiErrorCode::InvalidSponsor => 1036,
+            ApiErrorCode::UserAlreadyUserTrial => 1037,
+            ApiErrorCode::PlaylistExceeded => 1039,
+        };
+        Some(code)
+    }
+
+    /// Returns the name of the variant, e.g. `"InvalidAuthToken"`, for
+    /// reporting errors to other programs.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            ApiErrorCode::Unknown(_) => "Unknown",
+            ApiErrorCode::InternalError => "InternalError",
+            ApiErrorCode::MaintenanceMode => "MaintenanceMode",
+            ApiErrorCode::UrlParamMissingMethod => "UrlParamMissingMethod",
+            ApiErrorCode::UrlParamMissingAuthToken => "UrlParamMissingAuthToken",
+            ApiErrorCode::UrlParamMissingPartnerId => "UrlParamMissingPartnerId",
+            ApiErrorCode::UrlParamMissingUserId => "UrlParamMissingUserId",
+            ApiErrorCode::SecureProtocolRequired => "SecureProtocolRequired",
+            ApiErrorCode::CertifiateRequired => "CertifiateRequired",
+            ApiErrorCode::ParameterTypeMismatch => "ParameterTypeMismatch",
+            ApiErrorCode::ParameterMissing => "ParameterMissing",
+            ApiErrorCode::ParameterValueInvalid => "ParameterValueInvalid",
+            ApiErrorCode::ApiVersionNotSupported => "ApiVersionNotSupported",
+            ApiErrorCode::LicensingRestrictions => "LicensingRestrictions",
+            ApiErrorCode::InsufficientConnectivity => "InsufficientConnectivity",
+            ApiErrorCode::UnknownMethodName => "UnknownMethodName",
+            ApiErrorCode::WrongProtocol => "WrongProtocol",
+            ApiErrorCode::ReadOnlyMode => "ReadOnlyMode",
+            ApiErrorCode::InvalidAuthToken => "InvalidAuthToken",
+            ApiErrorCode::InvalidPartnerOrUserLogin => "InvalidPartnerOrUserLogin",
+            ApiErrorCode::ListenerNotAuthorized => "ListenerNotAuthorized",
+            ApiErrorCode::UserNotAuthorized => "UserNotAuthorized",
+            ApiErrorCode::MaxStationsReached => "MaxStationsReached",
+            ApiErrorCode::StationDoesNotExists => "StationDoesNotExists",
+            ApiErrorCode::ComplimentaryPeriodAlreadyInUse => "ComplimentaryPeriodAlreadyInUse",
+            ApiErrorCode::CallNotAllowed => "CallNotAllowed",
+            ApiErrorCode::DeviceNotFound => "DeviceNotFound",
+            ApiErrorCode::PartnerNotAuthroized => "PartnerNotAuthroized",
+            ApiErrorCode::InvalidUsername => "InvalidUsername",
+            ApiErrorCode::InvalidPassword => "InvalidPassword",
+            ApiErrorCode::UsernameAlreadyExists => "UsernameAlreadyExists",
+            ApiErrorCode::DeviceAlreadyAssociatedToAccount => "DeviceAlreadyAssociatedToAccount",
+            ApiErrorCode::UpgradeDeviceModelInvalid => "UpgradeDeviceModelInvalid",
+            ApiErrorCode::ExplicitPinIncorrect => "ExplicitPinIncorrect",
+            ApiErrorCode::ExplicitPinMalformed => "ExplicitPinMalformed",
+            ApiErrorCode::DeviceModelInvalid => "DeviceModelInvalid",
+            ApiErrorCode::ZipCodeInvalid => "ZipCodeInvalid",
+            ApiErrorCode::BirthYearInvalid => "BirthYearInvalid",
+            ApiErrorCode::BirthYearTooYoung => "BirthYearTooYoung",
+            ApiErrorCode::InvalidCountryCode => "InvalidCountryCode",
+            ApiErrorCode::InvalidGender => "InvalidGender",
+            ApiErrorCode::CannotRemoveAllSeeds => "CannotRemoveAllSeeds",
+            ApiErrorCode::DeviceDisabled => "DeviceDisabled",
+            ApiErrorCode::DailyTrialLimitReached => "DailyTrialLimitReached",
+            ApiErrorCode::InvalidSponsor => "InvalidSponsor",
+            ApiErrorCode::UserAlreadyUserTrial => "UserAlreadyUserTrial",
+            ApiErrorCode::PlaylistExceeded => "PlaylistExceeded",
+        }
+    }
+
+    /// Returns true for temporary server-side conditions where retrying
+    /// later may succeed.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            *self,
+            ApiErrorCode::InternalError
+                | ApiErrorCode::MaintenanceMode
+                | ApiErrorCode::InsufficientConnectivity
+                | ApiErrorCode::ReadOnlyMode
+        )
+    }
+
+    /// Returns true for errors caused by missing, invalid or expired
+    /// credentials.
+    pub fn is_auth_error(&self) -> bool {
+        matches!(
+            *self,
+            ApiErrorCode::UrlParamMissingAuthToken
+                | ApiErrorCode::UrlParamMissingPartnerId
+                | ApiErrorCode::UrlParamMissingUserId
+                | ApiErrorCode::InvalidAuthToken
+                | ApiErrorCode::InvalidPartnerOrUserLogin
+                | ApiErrorCode::ListenerNotAuthorized
+                | ApiErrorCode::UserNotAuthorized
+                | ApiErrorCode::PartnerNotAuthroized
+                | ApiErrorCode::InvalidUsername
+                | ApiErrorCode::InvalidPassword
+        )
+    }
+}
+
+impl fmt::Display for ApiErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let description = match *self {
+            ApiErrorCode::Unknown(Some(code)) => return write!(f, "unknown error {}", code),
+            ApiErrorCode::Unknown(None) => "unknown error",
+
+            ApiErrorCode::InternalError => "internal server error",
+            ApiErrorCode::MaintenanceMode => "Pandora is in maintenance mode",
+
+            ApiErrorCode::UrlParamMissingMethod => "method parameter missing",
+            ApiErrorCode::UrlParamMissingAuthToken => "auth token parameter missing",
+            ApiErrorCode::UrlParamMissingPartnerId => "partner id parameter missing",
+            ApiErrorCode::UrlParamMissingUserId => "user id parameter missing",
+
+            ApiErrorCode::SecureProtocolRequired => "HTTPS required",
+            ApiErrorCode::CertifiateRequired => "client certificate required",
+
+            ApiErrorCode::ParameterTypeMismatch => "parameter type mismatch",
+            ApiErrorCode::ParameterMissing => "parameter missing",
+            ApiErrorCode::ParameterValueInvalid => "invalid parameter value",
+
+            ApiErrorCode::ApiVersionNotSupported => "API version not supported",
+            ApiErrorCode::LicensingRestrictions => "Pandora is not available in this country",
+            ApiErrorCode::InsufficientConnectivity => "insufficient connectivity (bad sync time?)",
+
+            ApiErrorCode::UnknownMethodName => "unknown method name",
+            ApiErrorCode::WrongProtocol => "wrong protocol",
+
+            ApiErrorCode::ReadOnlyMode => "Pandora is in read-only mode",
+            ApiErrorCode::InvalidAuthToken => "invalid or expired auth token",
+            ApiErrorCode::InvalidPartnerOrUserLogin => "wrong username or password",
+            ApiErrorCode::ListenerNotAuthorized => {
+                "listener not authorized (subscription expired?)"
+            }
+            ApiErrorCode::UserNotAuthorized => "user not authorized",
+
+            ApiErrorCode::MaxStationsReached => "station limit reached",
+            ApiErrorCode::StationDoesNotExists => "station does not exist",
+
+            ApiErrorCode::ComplimentaryPeriodAlreadyInUse => "complimentary period already in use",
+            ApiErrorCode::CallNotAllowed => "call not allowed",
+            ApiErrorCode::DeviceNotFound => "device not found",
+            ApiErrorCode::PartnerNotAuthroized => "partner not authorized",
+
+            ApiErrorCode::InvalidUsername => "invalid username",
+            ApiErrorCode::InvalidPassword => "invalid password",
+            ApiErrorCode::UsernameAlreadyExists => "username already exists",
+
+            ApiErrorCode::DeviceAlreadyAssociatedToAccount => {
+                "device already associated to an account"
+            }
+            ApiErrorCode::UpgradeDeviceModelInvalid => "invalid upgrade device model",
+
+            ApiErrorCode::ExplicitPinIncorrect => "incorrect explicit content PIN",
+            ApiErrorCode::ExplicitPinMalformed => "malformed explicit content PIN",
+
+            ApiErrorCode::DeviceModelInvalid => "invalid device model",
+
+            ApiErrorCode::ZipCodeInvalid => "invalid ZIP code",
+            ApiErrorCode::BirthYearInvalid => "invalid birth year",
+            ApiErrorCode::BirthYearTooYoung => "user too young",
+            ApiErrorCode::InvalidCountryCode => "invalid country code",
+            ApiErrorCode::InvalidGender => "invalid gender",
+            ApiErrorCode::CannotRemoveAllSeeds => "cannot remove all seeds",
+            ApiErrorCode::DeviceDisabled => "device disabled",
+
+            ApiErrorCode::DailyTrialLimitReached => "daily trial limit reached",
+            ApiErrorCode::InvalidSponsor => "invalid sponsor",
+            ApiErrorCode::UserAlreadyUserTrial => "user already used the trial",
+
+            ApiErrorCode::PlaylistExceeded => "too many playlist requests",
+        };
+        f.write_str(description)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ApiErrorCode, Error};
+
+    use reqwest::Client;
+
+    use std::io;
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn only_failed_connects_are_connection_errors() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+
+        // The server reads the request, then hangs up without answering.
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let _ = io::Read::read(&mut stream, &mut [0; 1024]);
+        });
+        let err = Error::from(Client::new().post(&url).send().unwrap_err());
+        assert!(!err.is_connection_error(), "{}", err);
+        server.join().unwrap();
+
+        // Nothing listens on the port anymore.
+        let err = Error::from(Client::new().post(&url).send().unwrap_err());
+        assert!(err.is_connection_error(), "{}", err);
+
+        let err = Error::Io(io::Error::new(io::ErrorKind::TimedOut, "timed out"));
+        assert!(!err.is_connection_error());
+    }
+
+    #[test]
+    fn codes_round_trip() {
+        for code in 0..2000 {
+            assert_eq!(ApiErrorCode::from(code).code(), Some(code));
+        }
+        assert_eq!(ApiErrorCode::from(1028), ApiErrorCode::Unknown(Some(1028)));
+    }
+
+    #[test]
+    fn invalid_gender_is_told_apart_by_message() {
+        let gender = ApiErrorCode::from_response(Some(1027), "INVALID_GENDER");
+        assert_eq!(gender, ApiErrorCode::InvalidGender);
+        assert_eq!(gender.code(), Some(1027));
+        assert_eq!(
+            ApiErrorCode::from_response(Some(1027), "INVALID_COUNTRY_CODE"),
+            ApiErrorCode::InvalidCountryCode
+        );
+    }
+
+    #[test]
+    fn missing_code_is_unknown() {
+        let code = ApiErrorCode::from_response(None, "");
+        assert_eq!(code, ApiErrorCode::Unknown(None));
+        assert_eq!(code.code(), None);
+        assert!(!code.is_retryable());
+    }
+}
@@ -0,0 +1,551 @@
+//! Hooks run around every request a `Pandora` sends, for logging,
+//! metrics and tracing.
+//!
+//! Hooks are added with `Pandora::hook` and run in the order they were
+//! added. `LogHook` and `TracingHook` need the `log` and `tracing`
+//! features.
+
+use serde::de::DeserializeOwned;
+
+use super::error::{Error, Result};
+use super::method::Method;
+use super::protocol::HttpRequest;
+use super::request::exchange;
+use super::response::Stat;
+use super::transport::Transport;
+use super::Endpoint;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A request about to be sent.
+#[derive(Debug)]
+pub struct RequestInfo<'a> {
+    pub method: &'a Method,
+    pub endpoint: Endpoint<'static>,
+    /// JSON body before encryption, with secrets and account details
+    /// redacted.
+    pub body: String,
+}
+
+/// The outcome of a request.
+#[derive(Debug)]
+pub struct ResponseInfo<'a> {
+    pub method: &'a Method,
+    pub endpoint: Endpoint<'static>,
+    /// Time from sending the request to decoding the response.
+    pub latency: Duration,
+    /// HTTP status, unless the request failed before there was one.
+    pub status: Option<u16>,
+    /// `stat` of the response, unless it couldn't be decoded.
+    pub stat: Option<Stat>,
+    /// API error code of a failed call.
+    pub code: Option<u32>,
+    /// The error the request failed with, API errors included.
+    pub error: Option<&'a Error>,
+}
+
+impl<'a> ResponseInfo<'a> {
+    /// Returns true if the call succeeded.
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Hook called before each request is sent and after its response is
+/// received.
+///
+/// Logins done to renew an expired session are requests like any other.
+/// The first login, done by `Pandora::new` and the like before a hook can
+/// be added, isn't seen by hooks.
+///
+/// Hooks are shared by every thread using the `Pandora`, so they only get
+/// `&self`.
+pub trait Hook: Send + Sync {
+    fn before_send(&self, _request: &RequestInfo) {}
+
+    fn after_receive(&self, _response: &ResponseInfo) {}
+}
+
+impl<H> Hook for Arc<H>
+where
+    H: Hook + ?Sized,
+{
+    fn before_send(&self, request: &RequestInfo) {
+        (**self).before_send(request)
+    }
+
+    fn after_receive(&self, response: &ResponseInfo) {
+        (**self).after_receive(response)
+    }
+}
+
+/// Hooks of a `Pandora`.
+#[derive(Default)]
+pub(crate) struct Hooks(Vec<Box<dyn Hook>>);
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Hooks({})", self.0.len())
+    }
+}
+
+impl Hooks {
+    pub fn push(&mut self, hook: Box<dyn Hook>) {
+        self.0.push(hook);
+    }
+
+    /// Sends an encoded call with `request::exchange`, running the hooks
+    /// around it.
+    pub fn send<T, X>(
+        &self,
+        transport: &X,
+        endpoint: Endpoint<'static>,
+        request: &HttpRequest,
+    ) -> Result<T>
+    where
+        T: DeserializeOwned,
+        X: Transport + ?Sized,
+    {
+        if self.0.is_empty() {
+            return exchange(transport, request).1;
+        }
+
+        let info = RequestInfo {
+            method: &request.method,
+            endpoint,
+            body: request.redacted_body(),
+        };
+        for hook in &self.0 {
+            hook.before_send(&info);
+        }
+
+        let start = Instant::now();
+        let (status, result) = exchange(transport, request);
+        self.after_receive(request, endpoint, start.elapsed(), status, &result);
+        result
+    }
+
+    fn after_receive<T>(
+        &self,
+        request: &HttpRequest,
+        endpoint: Endpoint<'static>,
+        latency: Duration,
+        status: Option<u16>,
+        result: &Result<T>,
+    ) {
+        let (stat, code, error) = match result {
+            Ok(_) => (Some(Stat::Ok), None, None),
+            Err(err) => match err.api_code() {
+                Some(code) => (Some(Stat::Fail), code.code(), Some(err)),
+                None => (None, None, Some(err)),
+            },
+        };
+        let info = ResponseInfo {
+            method: &request.method,
+            endpoint,
+            latency,
+            status,
+            stat,
+            code,
+            error,
+        };
+        for hook in &self.0 {
+            hook.after_receive(&info);
+        }
+    }
+}
+
+/// Logs requests with the `log` crate: bodies at debug level, outcomes at
+/// info level and failures at warn level.
+#[cfg(feature = "log")]
+#[derive(Debug, Default)]
+pub struct LogHook;
+
+#[cfg(feature = "log")]
+impl Hook for LogHook {
+    fn before_send(&self, request: &RequestInfo) {
+        log::debug!(
+            "-> {} {} {}",
+            request.method,
+            request.endpoint,
+            request.body
+        );
+    }
+
+    fn after_receive(&self, response: &ResponseInfo) {
+        let status = response.status.map(|s| s.to_string());
+        let status = status.as_deref().unwrap_or("-");
+        let ms = response.latency.as_millis();
+        match response.error {
+            None => log::info!("<- {} {} ok {}ms", response.method, status, ms),
+            Some(err) => log::warn!("<- {} {} {} {}ms", response.method, status, err, ms),
+        }
+    }
+}
+
+/// Emits a `tracing` event for each request and response.
+#[cfg(feature = "tracing")]
+#[derive(Debug, Default)]
+pub struct TracingHook;
+
+#[cfg(feature = "tracing")]
+impl Hook for TracingHook {
+    fn before_send(&self, request: &RequestInfo) {
+        tracing::debug!(
+            method = %request.method,
+            endpoint = %request.endpoint,
+            body = %request.body,
+            "pandora request"
+        );
+    }
+
+    fn after_receive(&self, response: &ResponseInfo) {
+        let latency_ms = response.latency.as_millis() as u64;
+        match response.error {
+            None => tracing::info!(
+                method = %response.method,
+                status = response.status,
+                latency_ms,
+                "pandora response"
+            ),
+            Some(err) => tracing::warn!(
+                method = %response.method,
+                status = response.status,
+                code = response.code,
+                latency_ms,
+                error = %err,
+                "pandora request failed"
+            ),
+        }
+    }
+}
+
+/// Upper bounds, in milliseconds, of the latency histogram buckets. A last
+/// bucket counts the slower requests.
+pub const LATENCY_BUCKETS_MS: &[u64] = &[50, 100, 250, 500, 1000, 2500, 5000, 10000];
+
+/// Counts requests and their latency per method.
+///
+/// Add it as an `Arc` to keep a handle for reading the counts:
+/// `pandora.hook(Arc::clone(&metrics))`.
+#[derive(Debug, Default)]
+pub struct MetricsHook {
+    methods: Mutex<BTreeMap<String, MethodMetrics>>,
+}
+
+/// Request counts and latency of a method.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MethodMetrics {
+    pub requests: u64,
+    /// Calls the API answered with an error.
+    pub api_errors: u64,
+    /// Requests that failed without an API answer: connection and
+    /// decoding errors.
+    pub other_errors: u64,
+    /// Counts of API errors per error code, for the errors that had one.
+    pub codes: BTreeMap<u32, u64>,
+    /// Counts of responses per `LATENCY_BUCKETS_MS` bucket, plus one
+    /// for the slower ones.
+    pub latency_buckets: Vec<u64>,
+    pub total_latency: Duration,
+}
+
+impl MetricsHook {
+    pub fn new() -> Self {
+        MetricsHook::default()
+    }
+
+    /// Returns the metrics of every method called so far.
+    pub fn snapshot(&self) -> BTreeMap<String, MethodMetrics> {
+        self.methods.lock().unwrap().clone()
+    }
+}
+
+impl Hook for MetricsHook {
+    fn after_receive(&self, response: &ResponseInfo) {
+        let mut methods = self.methods.lock().unwrap();
+        let metrics = methods
+            .entry(response.method.to_string())
+            .or_insert_with(|| MethodMetrics {
+                latency_buckets: vec![0; LATENCY_BUCKETS_MS.len() + 1],
+                ..MethodMetrics::default()
+            });
+
+        metrics.requests += 1;
+        match (response.error, response.stat) {
+            (None, _) => {}
+            (Some(_), Some(Stat::Fail)) => {
+                metrics.api_errors += 1;
+                if let Some(code) = response.code {
+                    *metrics.codes.entry(code).or_insert(0) += 1;
+                }
+            }
+            (Some(_), _) => metrics.other_errors += 1,
+        }
+
+        let ms = response.latency.as_millis() as u64;
+        let bucket = LATENCY_BUCKETS_MS
+            .iter()
+            .position(|&bound| ms <= bound)
+            .unwrap_or(LATENCY_BUCKETS_MS.len());
+        metrics.latency_buckets[bucket] += 1;
+        metrics.total_latency += response.latency;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Hook, MetricsHook, RequestInfo, ResponseInfo};
+    use crate::error::{ApiErrorCode, Error, RequestContext};
+    use crate::method::Method;
+    use crate::response::Stat;
+    use crate::testing::{fail, ok, partner_login, Script};
+    use crate::DEFAULT_ENDPOINT;
+
+    use serde_json::{json, Value};
+
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    /// Sends a custom `method` through a `Pandora` with `hook` for each
+    /// response, ignoring the results.
+    fn send<H>(hook: H, method: &str, responses: Vec<Value>)
+    where
+        H: Hook + 'static,
+    {
+        let count = responses.len();
+        let pandora = Script::new(responses).pandora().hook(hook);
+        for _ in 0..count {
+            let _ = pandora.request_noop(Method::custom(method), Some(json!({ "id": 1 })));
+        }
+    }
+
+    fn response<'a>(
+        method: &'a Method,
+        latency_ms: u64,
+        error: Option<&'a Error>,
+    ) -> ResponseInfo<'a> {
+        ResponseInfo {
+            method,
+            endpoint: DEFAULT_ENDPOINT,
+            latency: Duration::from_millis(latency_ms),
+            status: Some(200),
+            stat: Some(if error.is_some() {
+                Stat::Fail
+            } else {
+                Stat::Ok
+            }),
+            code: error.and_then(Error::api_code).and_then(ApiErrorCode::code),
+            error,
+        }
+    }
+
+    /// Keeps the methods of the requests sent.
+    #[derive(Default)]
+    struct Sent(Mutex<Vec<Method>>);
+
+    impl Hook for Sent {
+        fn before_send(&self, request: &RequestInfo) {
+            self.0.lock().unwrap().push(request.method.clone());
+        }
+    }
+
+    #[test]
+    fn hooks_see_logins_after_an_expired_session() {
+        let sent = Arc::new(Sent::default());
+        let script = Script::new(vec![
+            fail(1001),
+            partner_login(),
+            json!({ "stat": "ok", "result": { "userAuthToken": "fresh-token" } }),
+            ok(),
+        ]);
+        let pandora = script.pandora().hook(Arc::clone(&sent));
+        pandora
+            .request_noop(Method::UserGetStationList, None)
+            .unwrap();
+
+        assert_eq!(*sent.0.lock().unwrap(), script.calls());
+        assert_eq!(
+            script.calls(),
+            [
+                Method::UserGetStationList,
+                Method::AuthPartnerLogin,
+                Method::AuthUserLogin,
+                Method::UserGetStationList,
+            ]
+        );
+    }
+
+    #[test]
+    fn metrics_count_outcomes_and_latency() {
+        let metrics = MetricsHook::new();
+        let method = Method::UserGetStationList;
+        let err = Error::Api {
+            message: String::new(),
+            code: ApiErrorCode::InvalidAuthToken,
+            context: Box::new(RequestContext::new(method.clone(), "", 200, "")),
+        };
+        metrics.after_receive(&response(&method, 20, None));
+        metrics.after_receive(&response(&method, 300, Some(&err)));
+        metrics.after_receive(&response(&method, 60000, None));
+
+        let snapshot = metrics.snapshot();
+        let stats = &snapshot["user.getStationList"];
+        assert_eq!(stats.requests, 3);
+        assert_eq!(stats.api_errors, 1);
+        assert_eq!(stats.codes[&1001], 1);
+        assert_eq!(stats.latency_buckets, [1, 0, 0, 1, 0, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn metrics_tell_api_errors_from_other_failures() {
+        let metrics = Arc::new(MetricsHook::new());
+        send(
+            Arc::clone(&metrics),
+            "test.metrics",
+            vec![
+                ok(),
+                fail(0),
+                json!({ "stat": "fail", "message": "no code" }),
+                json!("not a response"),
+            ],
+        );
+
+        let snapshot = metrics.snapshot();
+        let stats = &snapshot["test.metrics"];
+        assert_eq!(stats.requests, 4);
+        assert_eq!(stats.api_errors, 2);
+        assert_eq!(stats.other_errors, 1);
+        assert_eq!(stats.codes.len(), 1);
+        assert_eq!(stats.codes[&0], 1);
+    }
+
+    #[cfg(feature = "log")]
+    #[test]
+    fn log_hook_logs_requests_and_outcomes() {
+        use super::LogHook;
+
+        use log::{Level, LevelFilter, Log, Metadata, Record};
+
+        use std::sync::Mutex;
+
+        /// Keeps the records of this test's method.
+        struct Logger(Mutex<Vec<(Level, String)>>);
+
+        impl Log for Logger {
+            fn enabled(&self, _metadata: &Metadata) -> bool {
+                true
+            }
+
+            fn log(&self, record: &Record) {
+                let message = record.args().to_string();
+                if message.contains("test.logHook") {
+                    self.0.lock().unwrap().push((record.level(), message));
+                }
+            }
+
+            fn flush(&self) {}
+        }
+
+        static LOGGER: Logger = Logger(Mutex::new(Vec::new()));
+        log::set_logger(&LOGGER).unwrap();
+        log::set_max_level(LevelFilter::Debug);
+
+        send(LogHook, "test.logHook", vec![ok(), fail(0)]);
+
+        let records = LOGGER.0.lock().unwrap();
+        let levels: Vec<Level> = records.iter().map(|(level, _)| *level).collect();
+        assert_eq!(
+            levels,
+            [Level::Debug, Level::Info, Level::Debug, Level::Warn]
+        );
+        assert!(records[0].1.starts_with("-> test.logHook "));
+        assert!(records[0].1.contains(r#""id":1"#));
+        assert!(!records[0].1.contains("user-token"));
+        assert!(records[1].1.starts_with("<- test.logHook 200 ok "));
+        assert!(records[3].1.contains("failed"));
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn tracing_hook_emits_events() {
+        use super::TracingHook;
+
+        use tracing::field::{Field, Visit};
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Level, Metadata, Subscriber};
+
+        use std::fmt;
+        use std::sync::Mutex;
+
+        /// Names and values of the fields of an event.
+        struct Fields(Vec<(String, String)>);
+
+        /// Keeps the level and fields of every event.
+        #[derive(Clone, Default)]
+        struct Events(Arc<Mutex<Vec<(Level, Fields)>>>);
+
+        impl Visit for Fields {
+            fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+                self.0
+                    .push((field.name().to_owned(), format!("{:?}", value)));
+            }
+        }
+
+        impl Subscriber for Events {
+            fn enabled(&self, _metadata: &Metadata) -> bool {
+                true
+            }
+
+            fn new_span(&self, _span: &Attributes) -> Id {
+                Id::from_u64(1)
+            }
+
+            fn record(&self, _span: &Id, _values: &Record) {}
+
+            fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+            fn event(&self, event: &Event) {
+                let mut fields = Fields(Vec::new());
+                event.record(&mut fields);
+                let level = *event.metadata().level();
+                self.0.lock().unwrap().push((level, fields));
+            }
+
+            fn enter(&self, _span: &Id) {}
+
+            fn exit(&self, _span: &Id) {}
+        }
+
+        let events = Events::default();
+        tracing::subscriber::with_default(events.clone(), || {
+            send(TracingHook, "test.tracingHook", vec![ok(), fail(0)]);
+        });
+
+        let events = events.0.lock().unwrap();
+        let field = |index: usize, name: &str| {
+            let fields = &events[index].1;
+            fields
+                .0
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.clone())
+        };
+        let levels: Vec<Level> = events.iter().map(|(level, _)| *level).collect();
+        assert_eq!(
+            levels,
+            [Level::DEBUG, Level::INFO, Level::DEBUG, Level::WARN]
+        );
+        assert_eq!(field(0, "message").as_deref(), Some("pandora request"));
+        assert_eq!(field(0, "method").as_deref(), Some("test.tracingHook"));
+        assert_eq!(field(1, "status").as_deref(), Some("200"));
+        assert_eq!(field(3, "code").as_deref(), Some("0"));
+        assert_eq!(
+            field(3, "message").as_deref(),
+            Some("pandora request failed")
+        );
+    }
+}
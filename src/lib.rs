@@ -6,125 +6,302 @@ extern crate serde_json;
 
 use reqwest::Client;
 
+pub mod audio;
 pub mod auth;
+pub mod bookmarks;
+pub mod cassette;
 pub mod crypt;
+pub mod endpoint;
 pub mod error;
+pub mod hook;
 pub mod method;
 pub mod music;
 pub mod playlist;
+pub mod prefetch;
+pub mod protocol;
 pub mod request;
 pub mod response;
+pub mod session;
 pub mod stations;
+pub mod transport;
+
+#[cfg(test)]
+mod testing;
 
 pub use auth::Credentials;
+pub use bookmarks::Bookmarks;
+pub use endpoint::EndpointStrategy;
+pub use hook::Hook;
 pub use playlist::Track;
+pub use request::ApiRequest;
 pub use stations::Stations;
+pub use transport::Transport;
 
-use serde::de::DeserializeOwned;
+use serde::de::{DeserializeOwned, IgnoredAny};
 use serde_json::value::Value;
 
-use error::{Error, Result};
+use endpoint::Endpoints;
+use error::Result;
+use hook::Hooks;
 use method::Method;
-use request::request;
 
-use std::cell::RefCell;
-use std::sync::Mutex;
+use std::fmt;
+use std::sync::{Arc, Mutex, RwLock};
 
+/// Client for the Pandora API.
+///
+/// Requests only hold a lock long enough to take a snapshot of the
+/// credentials, so an `Arc<Pandora>` can serve many threads at once.
 #[derive(Debug)]
 pub struct Pandora {
+    /// Downloads audio.
     client: Client,
-    endpoint: Endpoint<'static>,
-    credentials: Mutex<RefCell<Credentials>>,
+    /// Sends API calls.
+    transport: Box<dyn Transport>,
+    endpoints: Endpoints,
+    hooks: Hooks,
+    credentials: RwLock<Arc<Credentials>>,
+    /// Held while logging in again, so only one thread does it.
+    relogin: Mutex<()>,
 }
 
 impl Pandora {
     pub fn new(username: &str, password: &str) -> Result<Self> {
-        let creds = Credentials::new(username, password)?;
-        Ok(Pandora::with_credentials(creds))
+        Pandora::with_endpoints(username, password, EndpointStrategy::default())
+    }
+
+    /// Logs in and sends every request through the endpoints of `strategy`.
+    ///
+    /// API calls and audio downloads share one HTTP client.
+    pub fn with_endpoints(
+        username: &str,
+        password: &str,
+        strategy: EndpointStrategy,
+    ) -> Result<Self> {
+        let client = Client::new();
+        Pandora::login(
+            username,
+            password,
+            strategy,
+            client.clone(),
+            Box::new(client),
+        )
+    }
+
+    /// Logs in and sends every API call, the login included, through
+    /// `transport` instead of HTTP.
+    pub fn with_transport<X>(
+        username: &str,
+        password: &str,
+        strategy: EndpointStrategy,
+        transport: X,
+    ) -> Result<Self>
+    where
+        X: Transport + 'static,
+    {
+        Pandora::login(
+            username,
+            password,
+            strategy,
+            Client::new(),
+            Box::new(transport),
+        )
     }
 
     pub fn with_credentials(credentials: Credentials) -> Self {
+        let client = Client::new();
+        Pandora::build(
+            client.clone(),
+            Box::new(client),
+            Endpoints::new(EndpointStrategy::default()),
+            credentials,
+        )
+    }
+
+    /// Sends API calls through `transport` instead of HTTP.
+    pub fn transport<X>(mut self, transport: X) -> Self
+    where
+        X: Transport + 'static,
+    {
+        self.transport = Box::new(transport);
+        self
+    }
+
+    /// Downloads audio with `client`, e.g. one with a proxy or timeouts.
+    ///
+    /// API calls keep going through the transport.
+    pub fn audio_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Sets how requests pick among tuner endpoints.
+    pub fn endpoint_strategy(mut self, strategy: EndpointStrategy) -> Self {
+        self.endpoints = Endpoints::new(strategy);
+        self
+    }
+
+    /// Adds a hook run around every request.
+    pub fn hook<H>(mut self, hook: H) -> Self
+    where
+        H: Hook + 'static,
+    {
+        self.hooks.push(Box::new(hook));
+        self
+    }
+
+    fn login(
+        username: &str,
+        password: &str,
+        strategy: EndpointStrategy,
+        client: Client,
+        transport: Box<dyn Transport>,
+    ) -> Result<Self> {
+        let endpoints = Endpoints::new(strategy);
+        let credentials = endpoints
+            .run(|endpoint| Credentials::login(&*transport, endpoint, username, password))?;
+        Ok(Pandora::build(client, transport, endpoints, credentials))
+    }
+
+    fn build(
+        client: Client,
+        transport: Box<dyn Transport>,
+        endpoints: Endpoints,
+        credentials: Credentials,
+    ) -> Self {
         Pandora {
-            client: Client::new(),
-            endpoint: DEFAULT_ENDPOINT,
-            credentials: Mutex::new(RefCell::new(credentials)),
+            client,
+            transport,
+            endpoints,
+            hooks: Hooks::default(),
+            credentials: RwLock::new(Arc::new(credentials)),
+            relogin: Mutex::new(()),
         }
     }
 
-    pub fn stations(&self) -> Stations {
+    /// Returns the endpoint requests are currently sent to.
+    pub fn endpoint(&self) -> Endpoint<'static> {
+        self.endpoints.current()
+    }
+
+    pub(crate) fn client(&self) -> &Client {
+        &self.client
+    }
+
+    pub fn stations(&self) -> Stations<'_> {
         Stations::new(self)
     }
 
+    pub fn bookmarks(&self) -> Bookmarks<'_> {
+        Bookmarks::new(self)
+    }
+
+    /// Sends an API call.
+    ///
+    /// A call rejected because of the credentials (`Error::is_auth_error`)
+    /// is retried once after logging in again; other errors are returned
+    /// as they are. If logging in again fails, the error of the call is
+    /// returned, not the one of the login.
     pub fn request<T>(&self, method: Method, body: Option<Value>) -> Result<T>
     where
         T: DeserializeOwned,
     {
-        let credentials = self.credentials.lock().unwrap();
-        let req = request(
-            &self.client,
-            self.endpoint,
-            method.clone(),
-            body.clone(),
-            Some(&credentials.borrow()),
-        );
-
-        match req {
-            Ok(res) => Ok(res),
-            Err(err) => {
-                if credentials.borrow_mut().refresh().is_err() {
-                    return Err(err);
-                }
-                request(
-                    &self.client,
-                    self.endpoint,
-                    method,
-                    body,
-                    Some(&credentials.borrow()),
-                )
-            }
-        }
+        let encrypted = method.is_encrypted();
+        self.send(method, encrypted, body)
     }
 
+    /// Sends a typed API call, retrying it like `request`.
+    pub fn call<R>(&self, request: &R) -> Result<R::Response>
+    where
+        R: ApiRequest,
+    {
+        let body = serde_json::to_value(request)?;
+        self.send(request.method(), request.is_encrypted(), Some(body))
+    }
+
+    /// Like `request`, for calls whose result, if any, is of no interest.
     pub fn request_noop(&self, method: Method, body: Option<Value>) -> Result<()> {
-        let credentials = self.credentials.lock().unwrap();
-
-        let req = request::<()>(
-            &self.client,
-            self.endpoint,
-            method.clone(),
-            body.clone(),
-            Some(&credentials.borrow()),
-        );
-
-        match req {
-            Ok(_) | Err(Error::Codec(_)) => Ok(()),
-            Err(err) => {
-                if credentials.borrow_mut().refresh().is_err() {
-                    return Err(err);
-                }
-                let req = request::<()>(
-                    &self.client,
-                    self.endpoint,
-                    method,
-                    body,
-                    Some(&credentials.borrow()),
-                );
-                match req {
-                    Ok(_) | Err(Error::Codec(_)) => Ok(()),
-                    Err(err) => Err(err),
-                }
-            }
+        self.request::<IgnoredAny>(method, body).map(|_| ())
+    }
+
+    fn send<T>(&self, method: Method, encrypted: bool, body: Option<Value>) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        let attempt = |credentials: &Credentials| {
+            self.endpoints.run(|endpoint| {
+                let request = protocol::encode(
+                    endpoint,
+                    method.clone(),
+                    encrypted,
+                    body.clone(),
+                    Some(credentials),
+                )?;
+                self.hooks.send(&*self.transport, endpoint, &request)
+            })
+        };
+
+        let credentials = self.credentials();
+        match attempt(&credentials) {
+            Err(err) if err.is_auth_error() => match self.relogin(&credentials) {
+                Ok(fresh) => attempt(&fresh),
+                Err(_) => Err(err),
+            },
+            req => req,
         }
     }
+
+    /// Returns the credentials currently in use.
+    fn credentials(&self) -> Arc<Credentials> {
+        Arc::clone(&self.credentials.read().unwrap())
+    }
+
+    /// Logs in again after `stale` was rejected and returns the new
+    /// credentials. Threads that were rejected at the same time wait for
+    /// the first one and reuse its login.
+    fn relogin(&self, stale: &Arc<Credentials>) -> Result<Arc<Credentials>> {
+        let _guard = self.relogin.lock().unwrap();
+        let current = self.credentials();
+        if !Arc::ptr_eq(&current, stale) {
+            return Ok(current);
+        }
+
+        let fresh = Arc::new(self.endpoints.run(|endpoint| {
+            Credentials::login_with_hooks(
+                &*self.transport,
+                &self.hooks,
+                endpoint,
+                stale.username(),
+                stale.password(),
+            )
+        })?);
+        *self.credentials.write().unwrap() = Arc::clone(&fresh);
+        Ok(fresh)
+    }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct Endpoint<'a>(&'a str);
 
-impl<'a> ToString for Endpoint<'a> {
-    fn to_string(&self) -> String {
+impl<'a> Endpoint<'a> {
+    pub fn is_https(&self) -> bool {
+        self.0.starts_with("https://")
+    }
+
+    /// Returns the HTTPS version of a plain HTTP endpoint from `ENDPOINTS`.
+    fn to_https(self) -> Option<Endpoint<'static>> {
+        let path = self.0.strip_prefix("http://")?;
+        ENDPOINTS
+            .iter()
+            .find(|endpoint| endpoint.0.strip_prefix("https://") == Some(path))
+            .cloned()
+    }
+}
+
+impl<'a> fmt::Display for Endpoint<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let Endpoint(url) = *self;
-        url.to_owned()
+        f.write_str(url)
     }
 }
 
@@ -135,3 +312,139 @@ pub const ENDPOINTS: [Endpoint<'static>; 4] = [
     Endpoint("https://internal-tuner.pandora.com/services/json/"),
 ];
 pub const DEFAULT_ENDPOINT: Endpoint<'static> = ENDPOINTS[1];
+
+#[cfg(test)]
+mod tests {
+    use super::Pandora;
+    use crate::error::{ApiErrorCode, Result};
+    use crate::method::Method;
+    use crate::protocol::{HttpRequest, HttpResponse};
+    use crate::testing::{credentials, SYNC_TIME};
+    use crate::transport::Transport;
+
+    use serde_json::json;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Barrier};
+    use std::thread;
+
+    const THREADS: usize = 8;
+
+    /// Transport rejecting the user token of `credentials()` and counting
+    /// the logins. The rejections wait for every thread to be rejected,
+    /// so they all try to log in again at once.
+    #[derive(Debug)]
+    struct ExpiredSession {
+        rejected: Barrier,
+        logins: AtomicUsize,
+    }
+
+    impl Transport for ExpiredSession {
+        fn execute(&self, request: &HttpRequest) -> Result<HttpResponse> {
+            let auth_token = request
+                .url
+                .query_pairs()
+                .find(|(key, _)| key == "auth_token")
+                .map(|(_, value)| value.into_owned());
+            let body = match request.method {
+                Method::AuthPartnerLogin => json!({
+                    "stat": "ok",
+                    "result": {
+                        "partnerId": "42",
+                        "partnerAuthToken": "partner-token",
+                        "syncTime": SYNC_TIME,
+                    },
+                }),
+                Method::AuthUserLogin => {
+                    self.logins.fetch_add(1, Ordering::SeqCst);
+                    json!({ "stat": "ok", "result": { "userAuthToken": "fresh-token" } })
+                }
+                _ if auth_token.as_deref() == Some("user-token") => {
+                    self.rejected.wait();
+                    json!({ "stat": "fail", "message": "expired", "code": 1001 })
+                }
+                _ => json!({ "stat": "ok", "result": { "checksum": auth_token } }),
+            };
+            Ok(HttpResponse {
+                status: 200,
+                body: body.to_string(),
+            })
+        }
+    }
+
+    /// Transport failing every call with `code`, and every login with
+    /// `InvalidPassword`. Counts the calls.
+    #[derive(Debug)]
+    struct Failing {
+        code: u32,
+        calls: AtomicUsize,
+    }
+
+    impl Transport for Failing {
+        fn execute(&self, request: &HttpRequest) -> Result<HttpResponse> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            let code = match request.method {
+                Method::AuthPartnerLogin | Method::AuthUserLogin => 1012,
+                _ => self.code,
+            };
+            Ok(HttpResponse {
+                status: 200,
+                body: json!({ "stat": "fail", "message": "failed", "code": code }).to_string(),
+            })
+        }
+    }
+
+    fn failing(code: u32) -> (Arc<Failing>, Result<()>) {
+        let transport = Arc::new(Failing {
+            code,
+            calls: AtomicUsize::new(0),
+        });
+        let pandora = Pandora::with_credentials(credentials()).transport(Arc::clone(&transport));
+        let result = pandora.request_noop(Method::UserGetStationList, None);
+        (transport, result)
+    }
+
+    #[test]
+    fn pandora_can_be_shared_across_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Pandora>();
+    }
+
+    #[test]
+    fn only_one_thread_logs_in_again() {
+        let transport = Arc::new(ExpiredSession {
+            rejected: Barrier::new(THREADS),
+            logins: AtomicUsize::new(0),
+        });
+        let pandora =
+            Arc::new(Pandora::with_credentials(credentials()).transport(Arc::clone(&transport)));
+
+        let threads: Vec<_> = (0..THREADS)
+            .map(|_| {
+                let pandora = Arc::clone(&pandora);
+                thread::spawn(move || pandora.stations().checksum().unwrap())
+            })
+            .collect();
+        for thread in threads {
+            assert_eq!(thread.join().unwrap().checksum, "fresh-token");
+        }
+        assert_eq!(transport.logins.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn failed_relogins_return_the_original_error() {
+        let (transport, result) = failing(1001);
+        let code = result.unwrap_err().api_code().cloned();
+        assert_eq!(code, Some(ApiErrorCode::InvalidAuthToken));
+        // The call and the partner login.
+        assert_eq!(transport.calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn only_auth_errors_log_in_again() {
+        let (transport, result) = failing(0);
+        let code = result.unwrap_err().api_code().cloned();
+        assert_eq!(code, Some(ApiErrorCode::InternalError));
+        assert_eq!(transport.calls.load(Ordering::SeqCst), 1);
+    }
+}
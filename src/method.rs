@@ -1,4 +1,6 @@
-#[derive(Clone)]
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Method {
     TestCheckLicensing,
 
@@ -9,6 +11,13 @@ pub enum Method {
 
     UserGetStationList,
     UserGetStationListChecksum,
+    UserSleepSong,
+    UserGetBookmarks,
+
+    BookmarkAddSongBookmark,
+    BookmarkAddArtistBookmark,
+    BookmarkDeleteSongBookmark,
+    BookmarkDeleteArtistBookmark,
 
     StationCreateStation,
     StationRenameStation,
@@ -20,40 +29,85 @@ pub enum Method {
 
     StationGetPlaylist,
     StationAddFeedback,
+
+    /// Any method the crate doesn't model, e.g. `"user.canSubscribe"`.
+    /// Call it with `Pandora::request::<serde_json::Value>` to get the raw
+    /// result.
+    Custom {
+        name: String,
+        encrypted: bool,
+    },
 }
 
-impl ToString for Method {
-    fn to_string(&self) -> String {
-        match *self {
-            Method::TestCheckLicensing => "test.checkLicensing".to_owned(),
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Method::Custom { ref name, .. } => name,
 
-            Method::AuthPartnerLogin => "auth.partnerLogin".to_owned(),
-            Method::AuthUserLogin => "auth.userLogin".to_owned(),
+            Method::TestCheckLicensing => "test.checkLicensing",
 
-            Method::MusicSearch => "music.search".to_owned(),
+            Method::AuthPartnerLogin => "auth.partnerLogin",
+            Method::AuthUserLogin => "auth.userLogin",
 
-            Method::UserGetStationList => "user.getStationList".to_owned(),
-            Method::UserGetStationListChecksum => "user.getStationListChecksum".to_owned(),
+            Method::MusicSearch => "music.search",
 
-            Method::StationCreateStation => "station.createStation".to_owned(),
-            Method::StationRenameStation => "station.renameStation".to_owned(),
-            Method::StationDeleteStation => "station.deleteStation".to_owned(),
+            Method::UserGetStationList => "user.getStationList",
+            Method::UserGetStationListChecksum => "user.getStationListChecksum",
+            Method::UserSleepSong => "user.sleepSong",
+            Method::UserGetBookmarks => "user.getBookmarks",
 
-            Method::StationGetStation => "station.getStation".to_owned(),
-            Method::StationAddMusic => "station.addMusic".to_owned(),
-            Method::StationDeleteMusic => "station.deleteMusic".to_owned(),
+            Method::BookmarkAddSongBookmark => "bookmark.addSongBookmark",
+            Method::BookmarkAddArtistBookmark => "bookmark.addArtistBookmark",
+            Method::BookmarkDeleteSongBookmark => "bookmark.deleteSongBookmark",
+            Method::BookmarkDeleteArtistBookmark => "bookmark.deleteArtistBookmark",
 
-            Method::StationGetPlaylist => "station.getPlaylist".to_owned(),
-            Method::StationAddFeedback => "station.addFeedback".to_owned(),
-        }
+            Method::StationCreateStation => "station.createStation",
+            Method::StationRenameStation => "station.renameStation",
+            Method::StationDeleteStation => "station.deleteStation",
+
+            Method::StationGetStation => "station.getStation",
+            Method::StationAddMusic => "station.addMusic",
+            Method::StationDeleteMusic => "station.deleteMusic",
+
+            Method::StationGetPlaylist => "station.getPlaylist",
+            Method::StationAddFeedback => "station.addFeedback",
+        };
+        f.write_str(name)
     }
 }
 
 impl Method {
+    /// Returns a custom method with an encrypted body, like most methods.
+    pub fn custom(name: &str) -> Self {
+        Method::Custom {
+            name: name.to_owned(),
+            encrypted: true,
+        }
+    }
+
     pub fn is_encrypted(&self) -> bool {
         match *self {
-            Method::TestCheckLicensing | Method::AuthPartnerLogin => false,
-            _ => true,
+            Method::Custom { encrypted, .. } => encrypted,
+            _ => !matches!(*self, Method::TestCheckLicensing | Method::AuthPartnerLogin),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Method;
+
+    #[test]
+    fn custom_methods_keep_their_name_and_encryption() {
+        let method = Method::custom("user.canSubscribe");
+        assert_eq!(method.to_string(), "user.canSubscribe");
+        assert!(method.is_encrypted());
+
+        let method = Method::Custom {
+            name: "test.echo".to_owned(),
+            encrypted: false,
+        };
+        assert_eq!(method.to_string(), "test.echo");
+        assert!(!method.is_encrypted());
+    }
+}
@@ -2,10 +2,9 @@
 
 use super::error::Result;
 use super::method::Method;
+use super::request::ApiRequest;
 use super::Pandora;
 
-use serde_json;
-
 /// Trait for types that can return a music token for seeding.
 pub trait ToMusicToken {
     fn to_music_token(&self) -> String;
@@ -26,7 +25,7 @@ pub enum MusicType {
 }
 
 /// Song information.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Song {
     #[serde(rename = "artistName")]
     pub artist_name: String,
@@ -44,7 +43,7 @@ impl ToMusicToken for Song {
 }
 
 /// Artist information.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Artist {
     #[serde(rename = "artistName")]
     pub artist_name: String,
@@ -70,9 +69,17 @@ struct Search {
     include_near_matches: bool,
 }
 
+impl ApiRequest for Search {
+    type Response = SearchResults;
+
+    fn method(&self) -> Method {
+        Method::MusicSearch
+    }
+}
+
 /// Search results with both the songs and the artists that matched
 /// the search string.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct SearchResults {
     #[serde(rename = "nearMatchesAvailable")]
     near_matches_available: bool,
@@ -87,12 +94,12 @@ impl SearchResults {
     }
 
     /// Returns the songs in the search results.
-    pub fn songs<'a>(&'a self) -> &'a [Song] {
+    pub fn songs(&self) -> &[Song] {
         &self.songs
     }
 
     /// Returns the artists in the search results.
-    pub fn artists<'a>(&'a self) -> &'a [Artist] {
+    pub fn artists(&self) -> &[Artist] {
         &self.artists
     }
 }
@@ -109,20 +116,14 @@ pub struct Music<'a> {
 impl<'a> Music<'a> {
     /// Creates a new Music handler.
     pub fn new(pandora: &'a Pandora) -> Music<'a> {
-        Music { pandora: pandora }
+        Music { pandora }
     }
 
     /// Searches for music using the given search string.
     pub fn search(&self, search_text: &str) -> Result<SearchResults> {
-        self.pandora.request(
-            Method::MusicSearch,
-            Some(
-                serde_json::to_value(Search {
-                    search_text: search_text.to_owned(),
-                    include_near_matches: true,
-                })
-                .unwrap(),
-            ),
-        )
+        self.pandora.call(&Search {
+            search_text: search_text.to_owned(),
+            include_near_matches: true,
+        })
     }
 }
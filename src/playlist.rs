@@ -1,11 +1,19 @@
-use super::error::Result;
+use super::error::{ApiErrorCode, Error, Result};
 use super::method::Method;
+use super::request::ApiRequest;
 use super::stations::ToStationToken;
 use super::Pandora;
 
-use serde_json;
+use serde::de::IgnoredAny;
 
-#[derive(Debug)]
+use std::collections::{BTreeMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// How long audio URLs returned by `station.getPlaylist` are assumed to
+/// stay valid. Pandora doesn't document it, so this errs on the short side.
+pub const AUDIO_URL_LIFETIME: Duration = Duration::from_secs(30 * 60);
+
+#[derive(Debug, Clone)]
 pub struct Playlist<'a> {
     pandora: &'a Pandora,
     station_token: String,
@@ -17,24 +25,26 @@ impl<'a> Playlist<'a> {
         T: ToStationToken,
     {
         Playlist {
-            pandora: pandora,
+            pandora,
             station_token: station.to_station_token(),
         }
     }
 
     /// Gets the current tracklist from Pandora.
     pub fn list(&self) -> Result<Vec<Track>> {
-        let tracklist = self.pandora.request::<Tracklist>(
-            Method::StationGetPlaylist,
-            Some(
-                serde_json::to_value(TracklistRequest {
-                    station_token: self.station_token.clone(),
-                    additional_audio_url: String::from("HTTP_128_MP3"),
-                })
-                .unwrap(),
-            ),
-        )?;
-        Ok(tracklist.items)
+        let tracklist = self.pandora.call(&TracklistRequest {
+            station_token: self.station_token.clone(),
+            additional_audio_url: String::from("HTTP_128_MP3"),
+        })?;
+        let fetched_at = Instant::now();
+        Ok(tracklist
+            .items
+            .into_iter()
+            .map(|track| Track {
+                fetched_at: Some(fetched_at),
+                ..track
+            })
+            .collect())
     }
 
     // TODO: Result should not be empty
@@ -43,25 +53,198 @@ impl<'a> Playlist<'a> {
     where
         T: ToTrackToken,
     {
-        self.pandora.request_noop(
-            Method::StationAddFeedback,
-            Some(
-                serde_json::to_value(RateTrackRequest {
-                    station_token: self.station_token.clone(),
-                    track_token: track.to_track_token().unwrap_or("".to_owned()),
-                    is_positive: is_positive,
-                })
-                .unwrap(),
-            ),
-        )
+        self.pandora
+            .call(&RateTrackRequest {
+                station_token: self.station_token.clone(),
+                track_token: track.to_track_token().unwrap_or("".to_owned()),
+                is_positive,
+            })
+            .map(|_| ())
+    }
+
+    /// Marks a track as "tired"; Pandora won't play it again for a month.
+    pub fn sleep<T>(&self, track: T) -> Result<()>
+    where
+        T: ToTrackToken,
+    {
+        self.pandora
+            .call(&SleepTrackRequest {
+                track_token: track.to_track_token().unwrap_or_default(),
+            })
+            .map(|_| ())
+    }
+
+    /// Turns the playlist into an endless iterator over its tracks.
+    pub fn tracks(self) -> Tracks<'a> {
+        Tracks::new(self)
+    }
+}
+
+impl<'a> IntoIterator for Playlist<'a> {
+    type Item = Result<Track>;
+    type IntoIter = Tracks<'a>;
+
+    fn into_iter(self) -> Tracks<'a> {
+        self.tracks()
+    }
+}
+
+/// Endless iterator over the tracks of a station.
+///
+/// Tracks are fetched in batches with `Playlist::list`, and the next batch
+/// is requested as soon as the queue drops to the prefetch threshold, so the
+/// queue never runs dry between two tracks. Iteration stops once Pandora
+/// refuses to hand out more tracks (`PlaylistExceeded` or a listening cap).
+/// Other errors are yielded once the queue is empty, and the batch is
+/// requested again on the next call, so iteration can go on after a
+/// transient failure.
+#[derive(Debug)]
+pub struct Tracks<'a> {
+    playlist: Playlist<'a>,
+    queue: VecDeque<Track>,
+    skip_ads: bool,
+    skip_expired: bool,
+    prefetch: usize,
+    exhausted: bool,
+}
+
+impl<'a> Tracks<'a> {
+    /// Creates a new iterator over the given playlist.
+    pub fn new(playlist: Playlist<'a>) -> Tracks<'a> {
+        Tracks {
+            playlist,
+            queue: VecDeque::new(),
+            skip_ads: false,
+            skip_expired: false,
+            prefetch: 1,
+            exhausted: false,
+        }
+    }
+
+    /// Skips ads instead of yielding them.
+    pub fn skip_ads(mut self, skip_ads: bool) -> Self {
+        self.skip_ads = skip_ads;
+        self
+    }
+
+    /// Drops queued tracks whose audio URLs have probably expired instead
+    /// of yielding them.
+    pub fn skip_expired(mut self, skip_expired: bool) -> Self {
+        self.skip_expired = skip_expired;
+        self
+    }
+
+    /// Sets how many tracks may be left in the queue before the next batch
+    /// is fetched.
+    pub fn prefetch_at(mut self, remaining: usize) -> Self {
+        self.prefetch = remaining;
+        self
+    }
+
+    /// Returns the playlist being iterated.
+    pub fn playlist(&self) -> &Playlist<'a> {
+        &self.playlist
+    }
+
+    /// Returns the tracks that have been fetched but not yielded yet.
+    pub fn queued(&self) -> &VecDeque<Track> {
+        &self.queue
+    }
+
+    /// Replaces queued tracks whose audio URLs have probably expired with
+    /// a fresh batch. Returns the number of tracks dropped.
+    pub fn refresh_expired(&mut self) -> Result<usize> {
+        let queued = self.queue.len();
+        let now = Instant::now();
+        self.queue.retain(|track| !track.is_probably_expired(now));
+        let dropped = queued - self.queue.len();
+        if dropped > 0 {
+            self.fill()?;
+        }
+        Ok(dropped)
+    }
+
+    /// Returns the track the next call to `next` will yield, fetching a
+    /// new batch if needed.
+    pub fn peek(&mut self) -> Result<Option<&Track>> {
+        match self.prepare() {
+            Some(err) => Err(err),
+            None => Ok(self.queue.front()),
+        }
+    }
+
+    /// Refills the queue if it's running low and drops the tracks that
+    /// shouldn't be yielded from its front. Returns the error to yield,
+    /// if any.
+    fn prepare(&mut self) -> Option<Error> {
+        loop {
+            if !self.exhausted && self.queue.len() <= self.prefetch {
+                match self.fill() {
+                    Ok(()) => {}
+                    Err(ref err) if ends_playlist(err) => self.exhausted = true,
+                    Err(err) if self.queue.is_empty() => return Some(err),
+                    // Queued tracks come first; the fetch is retried on the
+                    // next call.
+                    Err(_) => {}
+                }
+            }
+
+            match self.queue.front() {
+                Some(track)
+                    if (self.skip_ads && track.is_ad())
+                        || (self.skip_expired && track.is_probably_expired(Instant::now())) =>
+                {
+                    self.queue.pop_front();
+                }
+                _ => return None,
+            }
+        }
+    }
+
+    fn fill(&mut self) -> Result<()> {
+        let tracks = self.playlist.list()?;
+        self.queue.extend(tracks);
+        Ok(())
     }
 }
 
+impl<'a> Iterator for Tracks<'a> {
+    type Item = Result<Track>;
+
+    fn next(&mut self) -> Option<Result<Track>> {
+        if let Some(err) = self.prepare() {
+            return Some(Err(err));
+        }
+        self.queue.pop_front().map(Ok)
+    }
+}
+
+/// Returns true for the errors Pandora uses to signal that a station
+/// won't hand out any more tracks for now.
+fn ends_playlist(err: &Error) -> bool {
+    matches!(
+        *err,
+        Error::Api {
+            code: ApiErrorCode::PlaylistExceeded,
+            ..
+        } | Error::Api {
+            code: ApiErrorCode::DailyTrialLimitReached,
+            ..
+        }
+    )
+}
+
 /// Trait for types that return a track token.
 pub trait ToTrackToken {
     fn to_track_token(&self) -> Option<String>;
 }
 
+impl ToTrackToken for String {
+    fn to_track_token(&self) -> Option<String> {
+        Some(self.clone())
+    }
+}
+
 /// List of tracks.
 #[derive(Debug, Deserialize)]
 struct Tracklist {
@@ -70,7 +253,7 @@ struct Tracklist {
 
 /// Track information. Most fields are optional since
 /// the tracklist can include ads.
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Track {
     #[serde(rename = "trackToken")]
     pub track_token: Option<String>,
@@ -90,45 +273,124 @@ pub struct Track {
 
     #[serde(rename = "adToken")]
     pub ad_token: Option<String>,
+
+    /// When the track was fetched from Pandora.
+    #[serde(skip)]
+    fetched_at: Option<Instant>,
 }
 
 impl Track {
     pub fn is_ad(&self) -> bool {
         self.ad_token.is_some()
     }
+
+    /// Returns when the track was fetched by `Playlist::list`.
+    pub fn fetched_at(&self) -> Option<Instant> {
+        self.fetched_at
+    }
+
+    /// Returns true if the track's audio URLs are older than
+    /// `AUDIO_URL_LIFETIME` at `now` and likely rejected by Pandora's
+    /// servers. Tracks that weren't fetched by `Playlist::list` never
+    /// expire.
+    pub fn is_probably_expired(&self, now: Instant) -> bool {
+        match self.fetched_at {
+            Some(fetched_at) => now.saturating_duration_since(fetched_at) >= AUDIO_URL_LIFETIME,
+            None => false,
+        }
+    }
 }
 
 impl ToTrackToken for Track {
     fn to_track_token(&self) -> Option<String> {
-        match self.track_token {
-            Some(ref track_token) => Some(track_token.clone()),
-            None => None,
-        }
+        self.track_token.clone()
     }
 }
 
-impl<'a> ToTrackToken for &'a Track {
+impl ToTrackToken for &Track {
     fn to_track_token(&self) -> Option<String> {
-        match self.track_token {
-            Some(ref track_token) => Some(track_token.clone()),
-            None => None,
+        self.track_token.clone()
+    }
+}
+
+/// Quality level of a track's audio, as keyed in `audioUrlMap`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum Quality {
+    Low,
+    Medium,
+    High,
+    /// A level this crate doesn't know about, e.g. `"hifiQuality"`.
+    Other(String),
+}
+
+impl From<String> for Quality {
+    fn from(name: String) -> Self {
+        match name.as_str() {
+            "lowQuality" => Quality::Low,
+            "mediumQuality" => Quality::Medium,
+            "highQuality" => Quality::High,
+            _ => Quality::Other(name),
         }
     }
 }
 
-/// Struct for deserializing audio types for a track.
-#[derive(Debug, Clone, Deserialize)]
-pub struct TrackAudio {
-    #[serde(rename = "lowQuality")]
-    pub low_quality: Audio,
-    #[serde(rename = "mediumQuality")]
-    pub medium_quality: Audio,
-    #[serde(rename = "highQuality")]
-    pub high_quality: Audio,
+impl From<Quality> for String {
+    fn from(quality: Quality) -> Self {
+        match quality {
+            Quality::Low => "lowQuality".to_owned(),
+            Quality::Medium => "mediumQuality".to_owned(),
+            Quality::High => "highQuality".to_owned(),
+            Quality::Other(name) => name,
+        }
+    }
+}
+
+/// Audio of a track at each quality level Pandora returned; any of them
+/// may be missing.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct TrackAudio(BTreeMap<Quality, Audio>);
+
+impl TrackAudio {
+    pub fn get(&self, quality: &Quality) -> Option<&Audio> {
+        self.0.get(quality)
+    }
+
+    /// Returns the audio with the highest bitrate.
+    pub fn best(&self) -> Option<&Audio> {
+        self.ranked().next_back()
+    }
+
+    /// Returns the audio with the highest bitrate not above `kbps`.
+    pub fn at_most(&self, kbps: u32) -> Option<&Audio> {
+        self.ranked()
+            .rfind(|audio| audio.bitrate_kbps().is_some_and(|bitrate| bitrate <= kbps))
+    }
+
+    /// Iterates over the levels, from lowest to highest quality.
+    pub fn iter(&self) -> impl Iterator<Item = (&Quality, &Audio)> {
+        self.0.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Audio sorted by bitrate, then by quality level.
+    fn ranked(&self) -> impl DoubleEndedIterator<Item = &Audio> {
+        let mut audio: Vec<(&Quality, &Audio)> = self.0.iter().collect();
+        audio.sort_by_key(|&(quality, audio)| (audio.bitrate_kbps(), quality));
+        audio.into_iter().map(|(_, audio)| audio)
+    }
 }
 
 /// Audio information for a track.
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Audio {
     pub bitrate: String,
     pub encoding: String,
@@ -137,6 +399,13 @@ pub struct Audio {
     pub protocol: String,
 }
 
+impl Audio {
+    /// Returns the bitrate in kbps, if it's a number.
+    pub fn bitrate_kbps(&self) -> Option<u32> {
+        self.bitrate.parse().ok()
+    }
+}
+
 ////////////////////
 // Request structs
 ////////////////////
@@ -149,6 +418,14 @@ struct TracklistRequest {
     additional_audio_url: String,
 }
 
+impl ApiRequest for TracklistRequest {
+    type Response = Tracklist;
+
+    fn method(&self) -> Method {
+        Method::StationGetPlaylist
+    }
+}
+
 #[derive(Serialize)]
 pub struct RateTrackRequest {
     #[serde(rename = "stationToken")]
@@ -158,3 +435,120 @@ pub struct RateTrackRequest {
     #[serde(rename = "isPositive")]
     pub is_positive: bool,
 }
+
+impl ApiRequest for RateTrackRequest {
+    type Response = IgnoredAny;
+
+    fn method(&self) -> Method {
+        Method::StationAddFeedback
+    }
+}
+
+#[derive(Serialize)]
+struct SleepTrackRequest {
+    #[serde(rename = "trackToken")]
+    track_token: String,
+}
+
+impl ApiRequest for SleepTrackRequest {
+    type Response = IgnoredAny;
+
+    fn method(&self) -> Method {
+        Method::UserSleepSong
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Playlist, Track, AUDIO_URL_LIFETIME};
+    use crate::error::{ApiErrorCode, Result};
+    use crate::method::Method;
+    use crate::testing::{fail, tracks, Script};
+
+    use std::time::{Duration, Instant};
+
+    /// Describes each item: the track or ad token, or the error code.
+    fn outcomes<I>(items: I) -> Vec<String>
+    where
+        I: Iterator<Item = Result<Track>>,
+    {
+        items
+            .map(|item| match item {
+                Ok(track) => track.track_token.or(track.ad_token).unwrap(),
+                Err(err) => format!("error {:?}", err.api_code().and_then(ApiErrorCode::code)),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn keeps_going_after_errors_until_the_playlist_ends() {
+        let script = Script::new(vec![
+            tracks(&["a", "b"]),
+            fail(0),
+            tracks(&["c"]),
+            fail(1039),
+        ]);
+        let pandora = script.pandora();
+        let tracks = Playlist::new(&pandora, &"station".to_owned())
+            .tracks()
+            .prefetch_at(0);
+        assert_eq!(outcomes(tracks), ["a", "b", "error Some(0)", "c"]);
+        assert!(script
+            .calls()
+            .iter()
+            .all(|method| *method == Method::StationGetPlaylist));
+    }
+
+    #[test]
+    fn yields_queued_tracks_before_errors() {
+        let script = Script::new(vec![
+            tracks(&["a", "b"]),
+            fail(0),
+            fail(0),
+            tracks(&["c"]),
+            fail(1035),
+        ]);
+        let pandora = script.pandora();
+        let tracks = Playlist::new(&pandora, &"station".to_owned()).tracks();
+        assert_eq!(outcomes(tracks), ["a", "b", "error Some(0)", "c"]);
+    }
+
+    #[test]
+    fn skips_ads() {
+        let responses = vec![tracks(&["ad:x", "a", "ad:y"]), fail(1039)];
+        let pandora = Script::new(responses.clone()).pandora();
+        let tracks = Playlist::new(&pandora, &"station".to_owned()).tracks();
+        assert_eq!(outcomes(tracks), ["x", "a", "y"]);
+
+        let pandora = Script::new(responses).pandora();
+        let tracks = Playlist::new(&pandora, &"station".to_owned())
+            .tracks()
+            .skip_ads(true);
+        assert_eq!(outcomes(tracks), ["a"]);
+    }
+
+    #[test]
+    fn tracks_expire_after_the_audio_url_lifetime() {
+        let fetched_at = Instant::now();
+        let mut track: Track = serde_json::from_str(r#"{"trackToken":"a"}"#).unwrap();
+        assert!(!track.is_probably_expired(fetched_at + AUDIO_URL_LIFETIME * 2));
+
+        track.fetched_at = Some(fetched_at);
+        assert!(!track.is_probably_expired(fetched_at));
+        let second = Duration::from_secs(1);
+        assert!(!track.is_probably_expired(fetched_at + AUDIO_URL_LIFETIME - second));
+        assert!(track.is_probably_expired(fetched_at + AUDIO_URL_LIFETIME));
+    }
+
+    #[test]
+    fn listed_tracks_are_stamped_with_their_fetch_time() {
+        let pandora = Script::new(vec![tracks(&["a"])]).pandora();
+        let before = Instant::now();
+        let listed = Playlist::new(&pandora, &"station".to_owned())
+            .list()
+            .unwrap();
+        let fetched_at = listed[0].fetched_at().unwrap();
+        assert!(fetched_at >= before && fetched_at <= Instant::now());
+        assert!(listed[0].is_probably_expired(fetched_at + AUDIO_URL_LIFETIME));
+    }
+}
@@ -0,0 +1,301 @@
+//! Background prefetching of a track's audio for gapless transitions.
+//!
+//! While the current track plays, a `Prefetcher` downloads the beginning
+//! (or all) of the next one on a separate thread. The resulting
+//! `PrefetchedAudio` reads the buffered bytes first and then continues
+//! from the network where the prefetch stopped.
+
+use super::audio::AudioStream;
+use super::error::{Error, Result};
+use super::playlist::Audio;
+use super::Pandora;
+
+use reqwest::Client;
+
+#[cfg(feature = "tempfile")]
+use std::fs::File;
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+use std::thread::{self, JoinHandle};
+
+/// Default number of bytes prefetched, about half a minute of 128 kbps audio.
+pub const DEFAULT_BUDGET: u64 = 512 * 1024;
+
+/// Most bytes of memory reserved ahead of a download; the buffer grows
+/// past it as needed.
+const MAX_PREALLOCATION: u64 = DEFAULT_BUDGET;
+
+/// Where prefetched audio is kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Storage {
+    Memory,
+    /// An anonymous temporary file, removed once the audio is dropped.
+    /// Needs the `tempfile` feature.
+    #[cfg(feature = "tempfile")]
+    TempFile,
+}
+
+/// Starts background downloads of track audio.
+#[derive(Debug, Clone)]
+pub struct Prefetcher {
+    client: Client,
+    budget: u64,
+    storage: Storage,
+}
+
+impl Prefetcher {
+    /// Creates a prefetcher using the HTTP client of `pandora`.
+    pub fn new(pandora: &Pandora) -> Self {
+        Prefetcher::with_client(pandora.client().clone())
+    }
+
+    /// Creates a prefetcher using the given HTTP client.
+    pub fn with_client(client: Client) -> Self {
+        Prefetcher {
+            client,
+            budget: DEFAULT_BUDGET,
+            storage: Storage::Memory,
+        }
+    }
+
+    /// Sets the maximum number of bytes downloaded per track. Use
+    /// `u64::MAX` to download whole tracks.
+    pub fn budget(mut self, bytes: u64) -> Self {
+        self.budget = bytes;
+        self
+    }
+
+    /// Sets where the downloaded bytes are kept.
+    pub fn storage(mut self, storage: Storage) -> Self {
+        self.storage = storage;
+        self
+    }
+
+    /// Starts prefetching the given audio.
+    pub fn prefetch(&self, audio: &Audio) -> Prefetch {
+        self.prefetch_url(&audio.audio_url)
+    }
+
+    /// Starts prefetching an audio URL, e.g. a track's
+    /// `additional_audio_url`.
+    pub fn prefetch_url(&self, url: &str) -> Prefetch {
+        let client = self.client.clone();
+        let url = url.to_owned();
+        let budget = self.budget;
+        let storage = self.storage;
+        Prefetch {
+            handle: thread::spawn(move || download(client, &url, budget, storage)),
+        }
+    }
+}
+
+/// A prefetch running in the background.
+#[derive(Debug)]
+pub struct Prefetch {
+    handle: JoinHandle<Result<PrefetchedAudio>>,
+}
+
+impl Prefetch {
+    /// Returns true once the download finished or failed.
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    /// Waits for the download to finish.
+    pub fn wait(self) -> Result<PrefetchedAudio> {
+        match self.handle.join() {
+            Ok(result) => result,
+            Err(_) => Err(Error::Io(io::Error::other("prefetch thread panicked"))),
+        }
+    }
+}
+
+/// Audio whose beginning has been downloaded ahead of time.
+#[derive(Debug)]
+pub struct PrefetchedAudio {
+    buffer: Buffer,
+    buffered: u64,
+    /// Whether the download reached the end of the audio.
+    complete: bool,
+    stream: AudioStream,
+}
+
+impl PrefetchedAudio {
+    /// Returns the number of bytes downloaded ahead of time.
+    pub fn buffered(&self) -> u64 {
+        self.buffered
+    }
+
+    /// Returns true if the whole audio has been downloaded.
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    /// Returns the size of the audio in bytes, if the server reported it.
+    pub fn content_length(&self) -> Option<u64> {
+        self.stream.content_length()
+    }
+
+    /// Returns the MIME type of the audio, if the server reported it.
+    pub fn content_type(&self) -> Option<&str> {
+        self.stream.content_type()
+    }
+}
+
+impl Read for PrefetchedAudio {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.buffer.read(buf)? {
+            0 => self.stream.read(buf),
+            read => Ok(read),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Buffer {
+    Memory(Cursor<Vec<u8>>),
+    #[cfg(feature = "tempfile")]
+    File(File),
+}
+
+impl Read for Buffer {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            Buffer::Memory(ref mut cursor) => cursor.read(buf),
+            #[cfg(feature = "tempfile")]
+            Buffer::File(ref mut file) => file.read(buf),
+        }
+    }
+}
+
+impl Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match *self {
+            Buffer::Memory(ref mut cursor) => cursor.write(buf),
+            #[cfg(feature = "tempfile")]
+            Buffer::File(ref mut file) => file.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            Buffer::Memory(ref mut cursor) => cursor.flush(),
+            #[cfg(feature = "tempfile")]
+            Buffer::File(ref mut file) => file.flush(),
+        }
+    }
+}
+
+impl Seek for Buffer {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match *self {
+            Buffer::Memory(ref mut cursor) => cursor.seek(pos),
+            #[cfg(feature = "tempfile")]
+            Buffer::File(ref mut file) => file.seek(pos),
+        }
+    }
+}
+
+fn download(client: Client, url: &str, budget: u64, storage: Storage) -> Result<PrefetchedAudio> {
+    let mut stream = AudioStream::with_client(client, url)?;
+    let mut buffer = match storage {
+        Storage::Memory => {
+            let capacity = stream
+                .content_length()
+                .unwrap_or(budget)
+                .min(budget)
+                .min(MAX_PREALLOCATION);
+            Buffer::Memory(Cursor::new(Vec::with_capacity(capacity as usize)))
+        }
+        #[cfg(feature = "tempfile")]
+        Storage::TempFile => Buffer::File(tempfile::tempfile()?),
+    };
+
+    let buffered = io::copy(&mut stream.by_ref().take(budget), &mut buffer)?;
+    buffer.seek(SeekFrom::Start(0))?;
+    // Stopping short of the budget means the stream ended. Without a
+    // length, a download that used the whole budget may have more to come.
+    let complete = buffered < budget || stream.content_length() == Some(buffered);
+
+    Ok(PrefetchedAudio {
+        buffer,
+        buffered,
+        complete,
+        stream,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Prefetcher, Storage};
+    use crate::testing::{response, serve};
+
+    use reqwest::Client;
+
+    use std::io::Read;
+
+    fn audio() -> Vec<u8> {
+        (0..1000).map(|i| i as u8).collect()
+    }
+
+    fn prefetch(url: &str, budget: u64, storage: Storage) -> super::PrefetchedAudio {
+        Prefetcher::with_client(Client::new())
+            .budget(budget)
+            .storage(storage)
+            .prefetch_url(url)
+            .wait()
+            .unwrap()
+    }
+
+    fn read_all(mut audio: super::PrefetchedAudio) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        audio.read_to_end(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn stops_at_the_budget_and_streams_the_rest() {
+        let url = serve(|_| response("200 OK", &[("Content-Length", "1000")], &audio()));
+        let prefetched = prefetch(&url, 100, Storage::Memory);
+        assert_eq!(prefetched.buffered(), 100);
+        assert!(!prefetched.is_complete());
+        assert_eq!(read_all(prefetched), audio());
+    }
+
+    #[test]
+    fn downloads_whole_tracks_without_content_length() {
+        let url = serve(|_| response("200 OK", &[], &audio()));
+        let prefetched = prefetch(&url, u64::MAX, Storage::Memory);
+        assert_eq!(prefetched.buffered(), 1000);
+        assert_eq!(prefetched.content_length(), None);
+        assert!(prefetched.is_complete());
+        assert_eq!(read_all(prefetched), audio());
+    }
+
+    #[test]
+    fn chunked_audio_is_complete_only_once_read_to_the_end() {
+        let url = serve(|_| {
+            let mut body = format!("{:x}\r\n", audio().len()).into_bytes();
+            body.extend_from_slice(&audio());
+            body.extend_from_slice(b"\r\n0\r\n\r\n");
+            response("200 OK", &[("Transfer-Encoding", "chunked")], &body)
+        });
+
+        let prefetched = prefetch(&url, 1000, Storage::Memory);
+        assert_eq!(prefetched.content_length(), None);
+        assert!(!prefetched.is_complete());
+        assert_eq!(read_all(prefetched), audio());
+
+        let prefetched = prefetch(&url, 1001, Storage::Memory);
+        assert_eq!(prefetched.buffered(), 1000);
+        assert!(prefetched.is_complete());
+    }
+
+    #[cfg(feature = "tempfile")]
+    #[test]
+    fn buffers_to_a_temp_file() {
+        let url = serve(|_| response("200 OK", &[("Content-Length", "1000")], &audio()));
+        let prefetched = prefetch(&url, u64::MAX, Storage::TempFile);
+        assert!(prefetched.is_complete());
+        assert_eq!(read_all(prefetched), audio());
+    }
+}
@@ -0,0 +1,442 @@
+//! The Pandora JSON protocol without any I/O.
+//!
+//! `encode` turns a call into the URL and body to POST, and `decode` turns
+//! the HTTP response into a result or an `Error`. The blocking client in
+//! `request` is a thin layer over them; other HTTP clients or async
+//! runtimes can reuse them the same way.
+
+use serde::de::DeserializeOwned;
+use serde_json;
+use serde_json::value::Value;
+
+use url::Url;
+
+use super::error::{ApiErrorCode, Error, RequestContext, Result};
+use super::method::Method;
+use super::request::ApiRequest;
+use super::response::{Response, Stat};
+use super::Credentials;
+use super::Endpoint;
+
+/// An API call ready to be POSTed.
+#[derive(Debug, Clone)]
+pub struct HttpRequest {
+    pub method: Method,
+    pub endpoint: String,
+    /// URL with the method and auth query parameters.
+    pub url: Url,
+    /// JSON body, before encryption.
+    pub plain_body: String,
+    /// Body to send: the JSON body, encrypted if the call requires it.
+    pub body: String,
+}
+
+impl HttpRequest {
+    /// Returns the JSON body, before encryption, with secrets and personal
+    /// details redacted.
+    pub fn redacted_body(&self) -> String {
+        redact(&self.plain_body)
+    }
+
+    /// Returns the URL with its auth token and user ID redacted.
+    pub fn redacted_url(&self) -> Url {
+        let mut url = self.url.clone();
+        redact_query(&mut url);
+        url
+    }
+}
+
+/// What the server answered to an `HttpRequest`.
+#[derive(Debug, Clone)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub body: String,
+}
+
+/// Encodes a call, adding the auth parameters of `credentials` to the
+/// URL and body and encrypting the body if `encrypted` is true.
+pub fn encode(
+    endpoint: Endpoint,
+    method: Method,
+    encrypted: bool,
+    body: Option<Value>,
+    credentials: Option<&Credentials>,
+) -> Result<HttpRequest> {
+    let plain_body = serde_json::to_string(&build_body(body, credentials))?;
+    let body = match credentials {
+        Some(credentials) if encrypted => credentials.cipher().encrypt(&plain_body),
+        _ => plain_body.clone(),
+    };
+
+    Ok(HttpRequest {
+        url: build_url(endpoint, &method, credentials),
+        method,
+        endpoint: endpoint.to_string(),
+        plain_body,
+        body,
+    })
+}
+
+/// Encodes a typed call.
+pub fn encode_call<R>(
+    endpoint: Endpoint,
+    request: &R,
+    credentials: Option<&Credentials>,
+) -> Result<HttpRequest>
+where
+    R: ApiRequest,
+{
+    let body = serde_json::to_value(request)?;
+    encode(
+        endpoint,
+        request.method(),
+        request.is_encrypted(),
+        Some(body),
+        credentials,
+    )
+}
+
+/// Decodes the response to `request`.
+///
+/// A successful response without a result decodes as `null`, so calls
+/// whose result is optional or ignored succeed; for other types it's a
+/// `MissingResult` error.
+pub fn decode<T>(request: &HttpRequest, response: &HttpResponse) -> Result<T>
+where
+    T: DeserializeOwned,
+{
+    let context = || {
+        Box::new(RequestContext::new(
+            request.method.clone(),
+            &request.endpoint,
+            response.status,
+            &response.body,
+        ))
+    };
+
+    let res: Response<T> = match serde_json::from_str(&response.body) {
+        Ok(res) => res,
+        Err(source) => {
+            return Err(Error::Decode {
+                source,
+                context: context(),
+            })
+        }
+    };
+
+    match res {
+        Response {
+            stat: Stat::Ok,
+            result: Some(result),
+            ..
+        } => Ok(result),
+        Response {
+            stat: Stat::Ok,
+            result: None,
+            ..
+        } => T::deserialize(Value::Null).map_err(|_| Error::MissingResult { context: context() }),
+        Response {
+            stat: Stat::Fail,
+            message,
+            code,
+            ..
+        } => {
+            let message = message.unwrap_or_default();
+            let code = ApiErrorCode::from_response(code, &message);
+            Err(Error::Api {
+                message,
+                code,
+                context: context(),
+            })
+        }
+    }
+}
+
+/// Replaces secret values in redacted bodies and URLs.
+pub const REDACTED: &str = "<redacted>";
+
+/// Keys of JSON values that are redacted, wherever they are nested:
+/// passwords, tokens, and the account details of the user.
+const SECRET_KEYS: &[&str] = &[
+    "password",
+    "partnerAuthToken",
+    "userAuthToken",
+    "username",
+    "email",
+    "userId",
+    "listenerId",
+    "webname",
+    "userProfileUrl",
+    "zipCode",
+    "birthYear",
+    "gender",
+];
+
+/// Query parameters that are redacted, in request URLs and in URLs found
+/// in bodies, like the `auth` token of audio URLs.
+const SECRET_PARAMS: &[&str] = &["auth_token", "user_id", "auth"];
+
+/// Returns a JSON body with secrets and personal details redacted, so it
+/// can be logged or saved. A body that isn't JSON is returned unchanged.
+pub fn redact(body: &str) -> String {
+    match serde_json::from_str::<Value>(body) {
+        Ok(mut value) => {
+            redact_value(&mut value);
+            value.to_string()
+        }
+        Err(_) => body.to_owned(),
+    }
+}
+
+fn redact_value(value: &mut Value) {
+    match value {
+        Value::Object(obj) => {
+            for (key, value) in obj.iter_mut() {
+                if SECRET_KEYS.contains(&key.as_str()) {
+                    *value = Value::String(REDACTED.to_owned());
+                } else {
+                    redact_value(value);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(redact_value),
+        Value::String(string) if string.starts_with("http") => {
+            if let Ok(mut url) = Url::parse(string) {
+                if redact_query(&mut url) {
+                    *string = url.into_string();
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Redacts the `SECRET_PARAMS` of a URL. Returns true if it had any.
+fn redact_query(url: &mut Url) -> bool {
+    if !url
+        .query_pairs()
+        .any(|(key, _)| SECRET_PARAMS.contains(&key.as_ref()))
+    {
+        return false;
+    }
+
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(key, value)| {
+            let value = if SECRET_PARAMS.contains(&key.as_ref()) {
+                REDACTED.to_owned()
+            } else {
+                value.into_owned()
+            };
+            (key.into_owned(), value)
+        })
+        .collect();
+    url.query_pairs_mut().clear().extend_pairs(pairs);
+    true
+}
+
+/// Returns the JSON body with the auth parameters of `credentials`.
+pub fn build_body(body: Option<Value>, credentials: Option<&Credentials>) -> Value {
+    let mut body = match body {
+        Some(body) => body,
+        None => serde_json::to_value(serde_json::Map::<String, Value>::new())
+            .expect("Fatal error building body."),
+    };
+    if let Some(credentials) = credentials {
+        if let Some(obj) = body.as_object_mut() {
+            if let Some(partner_auth_token) = credentials.partner_auth_token() {
+                obj.insert(
+                    "partnerAuthToken".to_owned(),
+                    Value::String(partner_auth_token.to_owned()),
+                );
+            }
+            if let Some(sync_time) = credentials.sync_time() {
+                obj.insert("syncTime".to_owned(), Value::from(*sync_time));
+            }
+            if let Some(user_auth_token) = credentials.user_auth_token() {
+                obj.insert(
+                    "userAuthToken".to_owned(),
+                    Value::String(user_auth_token.to_owned()),
+                );
+            }
+        }
+    }
+    body
+}
+
+/// Returns the URL of a method with the auth query parameters of
+/// `credentials`.
+pub fn build_url(endpoint: Endpoint, method: &Method, credentials: Option<&Credentials>) -> Url {
+    let mut url = Url::parse(&endpoint.to_string()).unwrap();
+    // Encoded as a query value, so custom method names can't add
+    // parameters or a fragment.
+    url.query_pairs_mut()
+        .append_pair("method", &method.to_string());
+    if let Some(credentials) = credentials {
+        use std::collections::BTreeMap;
+        let mut query_pairs: BTreeMap<&str, &str> = BTreeMap::new();
+        if let Some(partner_auth_token) = credentials.partner_auth_token() {
+            query_pairs.insert("auth_token", partner_auth_token);
+        }
+        if let Some(user_auth_token) = credentials.user_auth_token() {
+            query_pairs.insert("auth_token", user_auth_token);
+        }
+        if let Some(partner_id) = credentials.partner_id() {
+            query_pairs.insert("partner_id", partner_id);
+        }
+        if let Some(user_id) = credentials.user_id() {
+            query_pairs.insert("user_id", user_id);
+        }
+        url.query_pairs_mut().extend_pairs(query_pairs);
+    }
+    url
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{build_url, decode, encode, redact, HttpRequest, HttpResponse, REDACTED};
+    use crate::error::{ApiErrorCode, Error};
+    use crate::method::Method;
+    use crate::stations::StationListChecksum;
+    use crate::testing::credentials;
+    use crate::DEFAULT_ENDPOINT;
+
+    use serde_json::{json, Value};
+
+    fn request() -> HttpRequest {
+        encode(
+            DEFAULT_ENDPOINT,
+            Method::UserGetStationListChecksum,
+            true,
+            None,
+            None,
+        )
+        .unwrap()
+    }
+
+    fn response(body: &str) -> HttpResponse {
+        HttpResponse {
+            status: 200,
+            body: body.to_owned(),
+        }
+    }
+
+    #[test]
+    fn encodes_without_credentials_in_plain_text() {
+        let request = request();
+        assert_eq!(
+            request.url.as_str(),
+            "https://tuner.pandora.com/services/json/?method=user.getStationListChecksum"
+        );
+        assert_eq!(request.plain_body, "{}");
+        assert_eq!(request.body, "{}");
+    }
+
+    #[test]
+    fn decodes_results_and_failures() {
+        let checksum: StationListChecksum = decode(
+            &request(),
+            &response(r#"{"stat":"ok","result":{"checksum":"abc"}}"#),
+        )
+        .unwrap();
+        assert_eq!(checksum.checksum, "abc");
+
+        let err = decode::<Value>(
+            &request(),
+            &response(r#"{"stat":"fail","message":"expired","code":1001}"#),
+        )
+        .unwrap_err();
+        assert_eq!(err.api_code(), Some(&ApiErrorCode::InvalidAuthToken));
+    }
+
+    #[test]
+    fn missing_result_decodes_as_null_when_possible() {
+        let ok = response(r#"{"stat":"ok"}"#);
+        assert_eq!(decode::<Value>(&request(), &ok).unwrap(), Value::Null);
+        match decode::<StationListChecksum>(&request(), &ok) {
+            Err(Error::MissingResult { .. }) => {}
+            res => panic!("expected MissingResult, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn redacts_secrets_and_personal_details() {
+        let body = r#"{"username":"me","password":"hunter2","result":{"userAuthToken":"abc"}}"#;
+        assert_eq!(
+            redact(body),
+            r#"{"password":"<redacted>","result":{"userAuthToken":"<redacted>"},"username":"<redacted>"}"#
+        );
+
+        let body = json!({
+            "result": {
+                "userId": "123",
+                "listenerId": "456",
+                "webname": "me",
+                "stationCount": 3,
+            }
+        });
+        let redacted: Value = serde_json::from_str(&redact(&body.to_string())).unwrap();
+        assert_eq!(redacted["result"]["userId"], REDACTED);
+        assert_eq!(redacted["result"]["listenerId"], REDACTED);
+        assert_eq!(redacted["result"]["webname"], REDACTED);
+        assert_eq!(redacted["result"]["stationCount"], 3);
+
+        assert_eq!(redact("not json"), "not json");
+    }
+
+    #[test]
+    fn redacts_auth_parameters_of_urls() {
+        let body = json!({
+            "items": [{
+                "audioUrl": "http://audio.example/a.mp4?version=5&auth=abc",
+                "artUrl": "http://art.example/a.jpg",
+            }]
+        });
+        let redacted: Value = serde_json::from_str(&redact(&body.to_string())).unwrap();
+        assert_eq!(
+            redacted["items"][0]["audioUrl"],
+            "http://audio.example/a.mp4?version=5&auth=%3Credacted%3E"
+        );
+        assert_eq!(redacted["items"][0]["artUrl"], "http://art.example/a.jpg");
+
+        let request = encode(
+            DEFAULT_ENDPOINT,
+            Method::UserGetStationList,
+            false,
+            None,
+            Some(&credentials()),
+        )
+        .unwrap();
+        let url = request.redacted_url();
+        let pairs: Vec<_> = url.query_pairs().into_owned().collect();
+        assert!(pairs.contains(&("auth_token".to_owned(), REDACTED.to_owned())));
+        assert!(pairs.contains(&("user_id".to_owned(), REDACTED.to_owned())));
+        assert!(pairs.contains(&("partner_id".to_owned(), "42".to_owned())));
+    }
+
+    #[test]
+    fn method_names_are_encoded_in_the_url() {
+        let method = Method::custom("a.b&auth_token=x#y");
+        let url = build_url(DEFAULT_ENDPOINT, &method, Some(&credentials()));
+        assert_eq!(url.fragment(), None);
+        let pairs: Vec<_> = url.query_pairs().into_owned().collect();
+        assert_eq!(
+            pairs[0],
+            ("method".to_owned(), "a.b&auth_token=x#y".to_owned())
+        );
+        let auth_tokens: Vec<_> = pairs
+            .iter()
+            .filter(|(key, _)| key == "auth_token")
+            .collect();
+        assert_eq!(
+            auth_tokens,
+            [&("auth_token".to_owned(), "user-token".to_owned())]
+        );
+
+        let url = build_url(DEFAULT_ENDPOINT, &Method::StationGetPlaylist, None);
+        assert_eq!(
+            url.as_str(),
+            format!("{}?method=station.getPlaylist", DEFAULT_ENDPOINT)
+        );
+    }
+}
@@ -1,20 +1,37 @@
 use serde::de::DeserializeOwned;
-use serde_json;
+use serde::Serialize;
 use serde_json::value::Value;
 
-use url::Url;
-
 use reqwest::{Body, Client};
 
-use super::crypt;
-use super::error::{Error, Result};
+use super::error::Result;
 use super::method::Method;
-use super::response::{Response, Stat};
+use super::protocol::{self, HttpRequest, HttpResponse};
+use super::transport::Transport;
 use super::Credentials;
 use super::Endpoint;
 
-pub fn request<T>(
-    client: &Client,
+/// A typed API call: the request body, serialized into the JSON sent to
+/// Pandora, along with its method and the type of its result.
+///
+/// Calls the crate lacks can be added by implementing this trait and
+/// passing them to `Pandora::call`.
+pub trait ApiRequest: Serialize {
+    /// Type the `result` of the response is decoded into. Calls without a
+    /// useful result can use `serde::de::IgnoredAny`.
+    type Response: DeserializeOwned;
+
+    fn method(&self) -> Method;
+
+    /// Returns true if the body is sent encrypted; defaults to what the
+    /// method requires.
+    fn is_encrypted(&self) -> bool {
+        self.method().is_encrypted()
+    }
+}
+
+pub fn request<T, X>(
+    transport: &X,
     endpoint: Endpoint,
     method: Method,
     body: Option<Value>,
@@ -22,97 +39,65 @@ pub fn request<T>(
 ) -> Result<T>
 where
     T: DeserializeOwned,
+    X: Transport + ?Sized,
 {
-    let mut body = serde_json::to_string(&build_body(body, credentials))?;
-
-    if method.is_encrypted() {
-        if let Some(credentials) = credentials {
-            body = crypt::encrypt(credentials.encrypt_key(), &body);
-        }
-    }
-
-    let url = build_url(client, endpoint, method, credentials);
-    let mut res_post = client
-        .post(&url.to_string())
-        .body(Body::from(body))
-        .send()?;
-    let res: Response<T> = res_post.json()?;
-
-    match res {
-        Response {
-            stat: Stat::Ok,
-            result: Some(result),
-            ..
-        } => Ok(result),
-        Response {
-            stat: Stat::Ok,
-            result: None,
-            ..
-        } => Err(Error::Io(<std::io::Error>::new(
-            std::io::ErrorKind::NotFound,
-            "Result not found.",
-        ))),
-        Response {
-            stat: Stat::Fail, ..
-        } => Err(Error::Api {
-            message: res.message.unwrap(),
-            code: res.code.unwrap().into(),
-        }),
-    }
+    let encrypted = method.is_encrypted();
+    send(transport, endpoint, method, encrypted, body, credentials)
 }
 
-fn build_body(body: Option<Value>, credentials: Option<&Credentials>) -> Value {
-    let mut body = match body {
-        Some(body) => body,
-        None => serde_json::to_value(serde_json::Map::<String, Value>::new())
-            .expect("Fatal error building body."),
-    };
-    if let Some(credentials) = credentials {
-        if let Some(obj) = body.as_object_mut() {
-            if let Some(partner_auth_token) = credentials.partner_auth_token() {
-                obj.insert(
-                    "partnerAuthToken".to_owned(),
-                    Value::String(partner_auth_token.to_owned()),
-                );
-            }
-            if let Some(sync_time) = credentials.sync_time() {
-                obj.insert("syncTime".to_owned(), Value::from(*sync_time));
-            }
-            if let Some(user_auth_token) = credentials.user_auth_token() {
-                obj.insert(
-                    "userAuthToken".to_owned(),
-                    Value::String(user_auth_token.to_owned()),
-                );
-            }
-        }
-    }
-    body
+/// Like `request`, encrypting the body only if `encrypted` is true.
+pub fn send<T, X>(
+    transport: &X,
+    endpoint: Endpoint,
+    method: Method,
+    encrypted: bool,
+    body: Option<Value>,
+    credentials: Option<&Credentials>,
+) -> Result<T>
+where
+    T: DeserializeOwned,
+    X: Transport + ?Sized,
+{
+    let request = protocol::encode(endpoint, method, encrypted, body, credentials)?;
+    exchange(transport, &request).1
 }
 
-fn build_url(
-    client: &Client,
+/// Sends a typed call.
+pub fn call<R, X>(
+    transport: &X,
     endpoint: Endpoint,
-    method: Method,
+    request: &R,
     credentials: Option<&Credentials>,
-) -> Url {
-    let url = format!("{}?method={}", endpoint.to_string(), method.to_string());
-    let mut url = Url::parse(&url).unwrap();
-    if let Some(credentials) = credentials {
-        use std::collections::BTreeMap;
-        let mut query_pairs: BTreeMap<&str, &str> = BTreeMap::new();
-        if let Some(partner_auth_token) = credentials.partner_auth_token() {
-            query_pairs.insert("auth_token", partner_auth_token);
-        }
-        if let Some(user_auth_token) = credentials.user_auth_token() {
-            query_pairs.insert("auth_token", user_auth_token);
-        }
-        if let Some(partner_id) = credentials.partner_id() {
-            query_pairs.insert("partner_id", partner_id);
-        }
-        if let Some(user_id) = credentials.user_id() {
-            query_pairs.insert("user_id", user_id);
-        }
-        url.query_pairs_mut().extend_pairs(query_pairs);
+) -> Result<R::Response>
+where
+    R: ApiRequest,
+    X: Transport + ?Sized,
+{
+    let request = protocol::encode_call(endpoint, request, credentials)?;
+    exchange(transport, &request).1
+}
+
+/// Sends an encoded call and decodes its response. Also returns the HTTP
+/// status, unless the request failed before there was one.
+pub fn exchange<T, X>(transport: &X, request: &HttpRequest) -> (Option<u16>, Result<T>)
+where
+    T: DeserializeOwned,
+    X: Transport + ?Sized,
+{
+    match transport.execute(request) {
+        Ok(response) => (Some(response.status), protocol::decode(request, &response)),
+        Err(err) => (None, Err(err)),
     }
-    url
+}
+
+/// POSTs an encoded call and reads the response.
+pub fn execute(client: &Client, request: &HttpRequest) -> Result<HttpResponse> {
+    let mut response = client
+        .post(request.url.as_str())
+        .body(Body::from(request.body.clone()))
+        .send()?;
+    Ok(HttpResponse {
+        status: response.status().as_u16(),
+        body: response.text()?,
+    })
 }
@@ -0,0 +1,457 @@
+//! Player-agnostic playback session on top of a station's tracks.
+//!
+//! A `Session` keeps track of what is playing, what played before and how
+//! far into the current track we are. It doesn't play any audio; players
+//! call `next` when a track ends and map their controls to `skip`,
+//! `thumb_up`, `thumb_down` and `tired`.
+
+use super::error::{Error, Result};
+use super::playlist::{Track, Tracks};
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Number of played tracks kept in the history.
+const HISTORY_LEN: usize = 50;
+
+/// Source of time for a session.
+pub trait Clock {
+    fn now(&self) -> Instant;
+}
+
+/// Clock backed by `Instant::now`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Maximum number of skips allowed within a sliding window.
+#[derive(Debug, Clone, Copy)]
+pub struct SkipLimit {
+    pub max_skips: usize,
+    pub window: Duration,
+}
+
+impl Default for SkipLimit {
+    /// Pandora's limit for free accounts: six skips per station per hour.
+    fn default() -> Self {
+        SkipLimit {
+            max_skips: 6,
+            window: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+/// Records skips and enforces a `SkipLimit`.
+#[derive(Debug, Clone)]
+pub struct SkipTracker {
+    limit: SkipLimit,
+    skips: VecDeque<Instant>,
+}
+
+impl SkipTracker {
+    pub fn new(limit: SkipLimit) -> Self {
+        SkipTracker {
+            limit,
+            skips: VecDeque::new(),
+        }
+    }
+
+    /// Returns the number of skips left at the given time.
+    pub fn remaining(&self, now: Instant) -> usize {
+        let used = self
+            .skips
+            .iter()
+            .filter(|&&skip| now.duration_since(skip) < self.limit.window)
+            .count();
+        self.limit.max_skips.saturating_sub(used)
+    }
+
+    /// Records a skip, or returns `Error::SkipLimitReached` with the time
+    /// until the oldest skip leaves the window.
+    pub fn try_skip(&mut self, now: Instant) -> Result<()> {
+        self.check(now)?;
+        self.record(now);
+        Ok(())
+    }
+
+    /// Returns `Error::SkipLimitReached` like `try_skip` if no skips are
+    /// left, without recording one.
+    pub fn check(&mut self, now: Instant) -> Result<()> {
+        let window = self.limit.window;
+        while let Some(&oldest) = self.skips.front() {
+            if now.duration_since(oldest) < window {
+                break;
+            }
+            self.skips.pop_front();
+        }
+
+        if self.skips.len() >= self.limit.max_skips {
+            let retry_after = match self.skips.front() {
+                Some(&oldest) => window - now.duration_since(oldest),
+                None => window,
+            };
+            return Err(Error::SkipLimitReached { retry_after });
+        }
+        Ok(())
+    }
+
+    /// Records a skip, whether or not any were left.
+    pub fn record(&mut self, now: Instant) {
+        self.skips.push_back(now);
+    }
+}
+
+/// Elapsed playback time of a track, honoring pauses.
+#[derive(Debug, Clone, Copy)]
+struct Elapsed {
+    started: Instant,
+    paused: Option<Instant>,
+}
+
+impl Elapsed {
+    fn start(now: Instant) -> Self {
+        Elapsed {
+            started: now,
+            paused: None,
+        }
+    }
+
+    fn position(&self, now: Instant) -> Duration {
+        self.paused.unwrap_or(now).duration_since(self.started)
+    }
+
+    fn pause(&mut self, now: Instant) {
+        if self.paused.is_none() {
+            self.paused = Some(now);
+        }
+    }
+
+    fn resume(&mut self, now: Instant) {
+        if let Some(paused) = self.paused.take() {
+            self.started += now.duration_since(paused);
+        }
+    }
+}
+
+/// Playback state of a station.
+#[derive(Debug)]
+pub struct Session<'a, C = SystemClock> {
+    tracks: Tracks<'a>,
+    clock: C,
+    skips: SkipTracker,
+    current: Option<(Track, Elapsed)>,
+    history: VecDeque<Track>,
+}
+
+impl<'a> Session<'a, SystemClock> {
+    /// Creates a session over the given tracks using the system clock.
+    pub fn new(tracks: Tracks<'a>) -> Self {
+        Session::with_clock(tracks, SystemClock)
+    }
+}
+
+impl<'a, C> Session<'a, C>
+where
+    C: Clock,
+{
+    /// Creates a session over the given tracks using a custom clock.
+    pub fn with_clock(tracks: Tracks<'a>, clock: C) -> Self {
+        Session {
+            tracks,
+            clock,
+            skips: SkipTracker::new(SkipLimit::default()),
+            current: None,
+            history: VecDeque::new(),
+        }
+    }
+
+    /// Replaces the default skip limit.
+    pub fn skip_limit(mut self, limit: SkipLimit) -> Self {
+        self.skips = SkipTracker::new(limit);
+        self
+    }
+
+    /// Returns the track currently playing.
+    pub fn current(&self) -> Option<&Track> {
+        self.current.as_ref().map(|(track, _)| track)
+    }
+
+    /// Returns the track that will play after the current one, e.g. to
+    /// prefetch its audio.
+    pub fn upcoming(&mut self) -> Result<Option<&Track>> {
+        self.tracks.peek()
+    }
+
+    /// Returns the previously played tracks, oldest first.
+    pub fn history(&self) -> &VecDeque<Track> {
+        &self.history
+    }
+
+    /// Returns how far into the current track playback is.
+    pub fn position(&self) -> Duration {
+        match self.current {
+            Some((_, ref elapsed)) => elapsed.position(self.clock.now()),
+            None => Duration::from_secs(0),
+        }
+    }
+
+    /// Returns the number of skips left right now.
+    pub fn remaining_skips(&self) -> usize {
+        self.skips.remaining(self.clock.now())
+    }
+
+    pub fn pause(&mut self) {
+        let now = self.clock.now();
+        if let Some((_, ref mut elapsed)) = self.current {
+            elapsed.pause(now);
+        }
+    }
+
+    pub fn resume(&mut self) {
+        let now = self.clock.now();
+        if let Some((_, ref mut elapsed)) = self.current {
+            elapsed.resume(now);
+        }
+    }
+
+    /// Moves on to the next track after the current one finished. Doesn't
+    /// count as a skip. Returns `None` once the station stops handing out
+    /// tracks.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<Option<&Track>> {
+        let next = match self.tracks.next() {
+            Some(track) => Some(track?),
+            None => None,
+        };
+
+        if let Some((track, _)) = self.current.take() {
+            if self.history.len() == HISTORY_LEN {
+                self.history.pop_front();
+            }
+            self.history.push_back(track);
+        }
+
+        let now = self.clock.now();
+        self.current = next.map(|track| (track, Elapsed::start(now)));
+        Ok(self.current())
+    }
+
+    /// Skips the current track, or returns `Error::SkipLimitReached`
+    /// without touching the session if no skips are left. The skip only
+    /// counts once the next track was fetched. With no current track this
+    /// is `next`, and isn't a skip at all.
+    pub fn skip(&mut self) -> Result<Option<&Track>> {
+        if self.current.is_none() {
+            return self.next();
+        }
+        let now = self.clock.now();
+        self.skips.check(now)?;
+        self.next()?;
+        self.skips.record(now);
+        Ok(self.current())
+    }
+
+    /// Rates the current track positively.
+    pub fn thumb_up(&mut self) -> Result<()> {
+        self.rate(true)
+    }
+
+    /// Rates the current track negatively and skips it. The rating is
+    /// kept even if the skip is refused with `Error::SkipLimitReached`.
+    pub fn thumb_down(&mut self) -> Result<Option<&Track>> {
+        self.rate(false)?;
+        self.skip()
+    }
+
+    /// Marks the current track as tired and skips it. The track stays
+    /// shelved even if the skip is refused with `Error::SkipLimitReached`.
+    pub fn tired(&mut self) -> Result<Option<&Track>> {
+        if let Some((ref track, _)) = self.current {
+            self.tracks.playlist().sleep(track)?;
+        }
+        self.skip()
+    }
+
+    fn rate(&mut self, is_positive: bool) -> Result<()> {
+        if let Some((ref mut track, _)) = self.current {
+            self.tracks.playlist().rate(&*track, is_positive)?;
+            track.song_rating = Some(if is_positive { 1 } else { 0 });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Clock, Elapsed, Error, Session, SkipLimit, SkipTracker};
+    use crate::method::Method;
+    use crate::playlist::{Playlist, Track, Tracks};
+    use crate::testing::{fail, ok, tracks, Script};
+    use crate::Pandora;
+
+    use std::cell::Cell;
+    use std::time::{Duration, Instant};
+
+    fn station(pandora: &Pandora) -> Tracks<'_> {
+        Playlist::new(pandora, &"station".to_owned())
+            .tracks()
+            .prefetch_at(0)
+    }
+
+    fn token(track: Option<&Track>) -> Option<&str> {
+        track.and_then(|track| track.track_token.as_deref())
+    }
+
+    struct ManualClock {
+        now: Cell<Instant>,
+    }
+
+    impl ManualClock {
+        fn new() -> Self {
+            ManualClock {
+                now: Cell::new(Instant::now()),
+            }
+        }
+
+        fn advance(&self, secs: u64) {
+            self.now.set(self.now.get() + Duration::from_secs(secs));
+        }
+    }
+
+    impl Clock for ManualClock {
+        fn now(&self) -> Instant {
+            self.now.get()
+        }
+    }
+
+    #[test]
+    fn skips_are_limited_per_window() {
+        let clock = ManualClock::new();
+        let mut tracker = SkipTracker::new(SkipLimit {
+            max_skips: 2,
+            window: Duration::from_secs(60),
+        });
+
+        assert!(tracker.try_skip(clock.now()).is_ok());
+        clock.advance(10);
+        assert!(tracker.try_skip(clock.now()).is_ok());
+        assert_eq!(tracker.remaining(clock.now()), 0);
+
+        clock.advance(20);
+        match tracker.try_skip(clock.now()) {
+            Err(Error::SkipLimitReached { retry_after }) => {
+                assert_eq!(retry_after, Duration::from_secs(30))
+            }
+            other => panic!("expected skip limit, got {:?}", other),
+        }
+
+        clock.advance(30);
+        assert_eq!(tracker.remaining(clock.now()), 1);
+        assert!(tracker.try_skip(clock.now()).is_ok());
+    }
+
+    #[test]
+    fn position_excludes_pauses() {
+        let clock = ManualClock::new();
+        let mut elapsed = Elapsed::start(clock.now());
+
+        clock.advance(5);
+        elapsed.pause(clock.now());
+        clock.advance(100);
+        assert_eq!(elapsed.position(clock.now()), Duration::from_secs(5));
+
+        elapsed.resume(clock.now());
+        clock.advance(3);
+        assert_eq!(elapsed.position(clock.now()), Duration::from_secs(8));
+    }
+
+    #[test]
+    fn next_moves_the_current_track_to_the_history() {
+        let script = Script::new(vec![tracks(&["a", "b"]), fail(1039)]);
+        let pandora = script.pandora();
+        let mut session = Session::new(station(&pandora));
+
+        assert_eq!(token(session.next().unwrap()), Some("a"));
+        assert_eq!(token(session.next().unwrap()), Some("b"));
+        assert_eq!(token(session.next().unwrap()), None);
+        let history: Vec<_> = session
+            .history()
+            .iter()
+            .map(|track| track.track_token.as_deref().unwrap())
+            .collect();
+        assert_eq!(history, ["a", "b"]);
+        assert_eq!(session.remaining_skips(), 6);
+    }
+
+    #[test]
+    fn failed_skips_are_not_counted() {
+        let script = Script::new(vec![tracks(&["a"]), fail(0), tracks(&["b"])]);
+        let pandora = script.pandora();
+        let mut session = Session::new(station(&pandora)).skip_limit(SkipLimit {
+            max_skips: 1,
+            window: Duration::from_secs(60),
+        });
+        session.next().unwrap();
+
+        assert!(session.skip().is_err());
+        assert_eq!(token(session.current()), Some("a"));
+        assert_eq!(session.remaining_skips(), 1);
+
+        assert_eq!(token(session.skip().unwrap()), Some("b"));
+        assert_eq!(session.remaining_skips(), 0);
+        match session.skip() {
+            Err(Error::SkipLimitReached { .. }) => {}
+            other => panic!("expected skip limit, got {:?}", other),
+        }
+        assert_eq!(token(session.current()), Some("b"));
+        assert_eq!(script.calls().len(), 3);
+    }
+
+    #[test]
+    fn skipping_without_a_current_track_is_not_counted() {
+        let script = Script::new(vec![tracks(&["a", "b"])]);
+        let pandora = script.pandora();
+        let mut session = Session::new(station(&pandora)).skip_limit(SkipLimit {
+            max_skips: 1,
+            window: Duration::from_secs(60),
+        });
+
+        assert_eq!(token(session.skip().unwrap()), Some("a"));
+        assert_eq!(session.remaining_skips(), 1);
+        assert!(session.history().is_empty());
+
+        assert_eq!(token(session.skip().unwrap()), Some("b"));
+        assert_eq!(session.remaining_skips(), 0);
+    }
+
+    #[test]
+    fn thumbs_rate_the_current_track() {
+        let script = Script::new(vec![tracks(&["a", "b", "c"]), ok(), ok(), ok()]);
+        let pandora = script.pandora();
+        let mut session = Session::new(station(&pandora));
+        session.next().unwrap();
+
+        session.thumb_up().unwrap();
+        assert_eq!(session.current().unwrap().song_rating, Some(1));
+
+        assert_eq!(token(session.thumb_down().unwrap()), Some("b"));
+        assert_eq!(session.history().back().unwrap().song_rating, Some(0));
+        assert_eq!(token(session.tired().unwrap()), Some("c"));
+        assert_eq!(session.remaining_skips(), 4);
+
+        assert_eq!(
+            script.calls(),
+            [
+                Method::StationGetPlaylist,
+                Method::StationAddFeedback,
+                Method::StationAddFeedback,
+                Method::UserSleepSong,
+            ]
+        );
+    }
+}
@@ -2,9 +2,10 @@ use super::error::Result;
 use super::method::Method;
 use super::music::{MusicType, ToMusicToken};
 use super::playlist::Playlist;
+use super::request::ApiRequest;
 use super::Pandora;
 
-use serde_json;
+use serde::de::IgnoredAny;
 
 pub struct Stations<'a> {
     pandora: &'a Pandora,
@@ -12,13 +13,11 @@ pub struct Stations<'a> {
 
 impl<'a> Stations<'a> {
     pub fn new(pandora: &'a Pandora) -> Stations<'a> {
-        Stations { pandora: pandora }
+        Stations { pandora }
     }
 
     pub fn list(&self) -> Result<Vec<Station>> {
-        let stations = self
-            .pandora
-            .request::<StationList>(Method::UserGetStationList, None)?;
+        let stations = self.pandora.call(&GetStationListRequest {})?;
         Ok(stations.stations)
     }
 
@@ -26,48 +25,32 @@ impl<'a> Stations<'a> {
     where
         T: ToMusicToken,
     {
-        self.pandora.request(
-            Method::StationCreateStation,
-            Some(
-                serde_json::to_value(CreateStationRequest {
-                    track_token: None,
-                    music_type: None,
-                    music_token: Some(music_token.to_music_token()),
-                })
-                .unwrap(),
-            ),
-        )
+        self.pandora.call(&CreateStationRequest {
+            track_token: None,
+            music_type: None,
+            music_token: Some(music_token.to_music_token()),
+        })
     }
 
     pub fn rename<T>(&self, station: &T, station_name: &str) -> Result<Station>
     where
         T: ToStationToken,
     {
-        self.pandora.request(
-            Method::StationRenameStation,
-            Some(
-                serde_json::to_value(RenameStationRequest {
-                    station_token: station.to_station_token(),
-                    station_name: station_name.to_owned(),
-                })
-                .unwrap(),
-            ),
-        )
+        self.pandora.call(&RenameStationRequest {
+            station_token: station.to_station_token(),
+            station_name: station_name.to_owned(),
+        })
     }
 
     pub fn delete<T>(&self, station: &T) -> Result<()>
     where
         T: ToStationToken,
     {
-        self.pandora.request_noop(
-            Method::StationDeleteStation,
-            Some(
-                serde_json::to_value(DeleteStationRequest {
-                    station_token: station.to_station_token(),
-                })
-                .unwrap(),
-            ),
-        )
+        self.pandora
+            .call(&DeleteStationRequest {
+                station_token: station.to_station_token(),
+            })
+            .map(|_| ())
     }
 
     pub fn add_seed<S, T>(&self, station: &S, music_token: &T) -> Result<Seed>
@@ -75,53 +58,36 @@ impl<'a> Stations<'a> {
         S: ToStationToken,
         T: ToMusicToken,
     {
-        self.pandora.request(
-            Method::StationAddMusic,
-            Some(
-                serde_json::to_value(AddSeedRequest {
-                    station_token: station.to_station_token(),
-                    music_token: music_token.to_music_token(),
-                })
-                .unwrap(),
-            ),
-        )
+        self.pandora.call(&AddSeedRequest {
+            station_token: station.to_station_token(),
+            music_token: music_token.to_music_token(),
+        })
     }
 
     pub fn remove_seed(&self, seed: &Seed) -> Result<()> {
-        self.pandora.request(
-            Method::StationDeleteMusic,
-            Some(
-                serde_json::to_value(RemoveSeedRequest {
-                    seed_id: seed.seed_id.clone(),
-                })
-                .unwrap(),
-            ),
-        )
+        self.pandora
+            .call(&RemoveSeedRequest {
+                seed_id: seed.seed_id.clone(),
+            })
+            .map(|_| ())
     }
 
     pub fn station<T>(&self, station: &T) -> Result<Station>
     where
         T: ToStationToken,
     {
-        self.pandora.request(
-            Method::StationGetStation,
-            Some(
-                serde_json::to_value(GetStationRequest {
-                    station_token: station.to_station_token(),
-                    include_extended_attributes: true,
-                })
-                .unwrap(),
-            ),
-        )
+        self.pandora.call(&GetStationRequest {
+            station_token: station.to_station_token(),
+            include_extended_attributes: true,
+        })
     }
 
     // Gets the current checksum of the station; useful if you need to check for changes.
     pub fn checksum(&self) -> Result<StationListChecksum> {
-        self.pandora
-            .request(Method::UserGetStationListChecksum, None)
+        self.pandora.call(&GetStationListChecksumRequest {})
     }
 
-    pub fn playlist<T>(&self, station: &T) -> Playlist
+    pub fn playlist<T>(&self, station: &T) -> Playlist<'_>
     where
         T: ToStationToken,
     {
@@ -133,7 +99,13 @@ pub trait ToStationToken {
     fn to_station_token(&self) -> String;
 }
 
-#[derive(Debug, Clone, Deserialize)]
+impl ToStationToken for String {
+    fn to_station_token(&self) -> String {
+        self.clone()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Station {
     #[serde(rename = "stationId")]
     pub station_id: String,
@@ -147,18 +119,18 @@ impl ToStationToken for Station {
     }
 }
 
-#[derive(Debug, Deserialize)]
-struct StationList {
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StationList {
     pub stations: Vec<Station>,
     pub checksum: String,
 }
 
-#[derive(Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct StationListChecksum {
     pub checksum: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct ExtendedStation {
     #[serde(rename = "stationId")]
     pub station_id: String,
@@ -171,7 +143,7 @@ pub struct ExtendedStation {
 }
 
 /// Seed information for a station.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct StationMusic {
     pub songs: Vec<SongSeed>,
     pub artists: Vec<ArtistSeed>,
@@ -179,14 +151,14 @@ pub struct StationMusic {
 }
 
 /// Generic seed.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Seed {
     #[serde(rename = "seedId")]
     pub seed_id: String,
 }
 
 /// Song seed.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct SongSeed {
     #[serde(rename = "seedId")]
     pub seed_id: String,
@@ -201,7 +173,7 @@ pub struct SongSeed {
 }
 
 /// Artist seed.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct ArtistSeed {
     #[serde(rename = "seedId")]
     pub seed_id: String,
@@ -214,7 +186,7 @@ pub struct ArtistSeed {
 }
 
 /// Genre seed.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct GenreSeed {
     #[serde(rename = "seedId")]
     pub seed_id: String,
@@ -228,6 +200,28 @@ pub struct GenreSeed {
 // Request structs
 ////////////////////
 
+#[derive(Serialize)]
+struct GetStationListRequest {}
+
+impl ApiRequest for GetStationListRequest {
+    type Response = StationList;
+
+    fn method(&self) -> Method {
+        Method::UserGetStationList
+    }
+}
+
+#[derive(Serialize)]
+struct GetStationListChecksumRequest {}
+
+impl ApiRequest for GetStationListChecksumRequest {
+    type Response = StationListChecksum;
+
+    fn method(&self) -> Method {
+        Method::UserGetStationListChecksum
+    }
+}
+
 #[derive(Serialize)]
 struct CreateStationRequest {
     #[serde(rename = "trackToken")]
@@ -238,6 +232,14 @@ struct CreateStationRequest {
     music_token: Option<String>,
 }
 
+impl ApiRequest for CreateStationRequest {
+    type Response = Station;
+
+    fn method(&self) -> Method {
+        Method::StationCreateStation
+    }
+}
+
 #[derive(Serialize)]
 struct RenameStationRequest {
     #[serde(rename = "stationToken")]
@@ -246,12 +248,28 @@ struct RenameStationRequest {
     station_name: String,
 }
 
+impl ApiRequest for RenameStationRequest {
+    type Response = Station;
+
+    fn method(&self) -> Method {
+        Method::StationRenameStation
+    }
+}
+
 #[derive(Serialize)]
 struct DeleteStationRequest {
     #[serde(rename = "stationToken")]
     station_token: String,
 }
 
+impl ApiRequest for DeleteStationRequest {
+    type Response = IgnoredAny;
+
+    fn method(&self) -> Method {
+        Method::StationDeleteStation
+    }
+}
+
 #[derive(Serialize)]
 struct GetStationRequest {
     #[serde(rename = "stationToken")]
@@ -260,6 +278,14 @@ struct GetStationRequest {
     include_extended_attributes: bool,
 }
 
+impl ApiRequest for GetStationRequest {
+    type Response = Station;
+
+    fn method(&self) -> Method {
+        Method::StationGetStation
+    }
+}
+
 #[derive(Serialize)]
 struct AddSeedRequest {
     #[serde(rename = "stationToken")]
@@ -268,8 +294,48 @@ struct AddSeedRequest {
     music_token: String,
 }
 
+impl ApiRequest for AddSeedRequest {
+    type Response = Seed;
+
+    fn method(&self) -> Method {
+        Method::StationAddMusic
+    }
+}
+
 #[derive(Serialize)]
 struct RemoveSeedRequest {
     #[serde(rename = "seedId")]
     seed_id: String,
 }
+
+impl ApiRequest for RemoveSeedRequest {
+    type Response = IgnoredAny;
+
+    fn method(&self) -> Method {
+        Method::StationDeleteMusic
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Seed;
+    use crate::method::Method;
+    use crate::testing::{ok, Script};
+
+    use serde_json::json;
+
+    #[test]
+    fn removing_a_seed_needs_no_result() {
+        let script = Script::new(vec![ok(), json!({ "stat": "ok", "result": {} })]);
+        let pandora = script.pandora();
+        let seed = Seed {
+            seed_id: "s1".to_owned(),
+        };
+        pandora.stations().remove_seed(&seed).unwrap();
+        pandora.stations().remove_seed(&seed).unwrap();
+        assert_eq!(
+            script.calls(),
+            [Method::StationDeleteMusic, Method::StationDeleteMusic]
+        );
+    }
+}
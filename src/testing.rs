@@ -0,0 +1,172 @@
+//! Test helpers: credentials of a logged in user, a scripted transport
+//! and a minimal HTTP server.
+
+use serde_json::{json, Value};
+
+use super::auth::{Partner, PartnerLogin, UserLogin};
+use super::error::Result;
+use super::method::Method;
+use super::protocol::{HttpRequest, HttpResponse};
+use super::transport::Transport;
+use super::{Credentials, Pandora};
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+/// An encrypted `syncTime` the android partner's keys decrypt.
+pub const SYNC_TIME: &str = "87f9b461070d0efd5a143d430aafebf9";
+
+/// Credentials of a logged in user, with partner ID `42` and user ID
+/// `123`.
+pub fn credentials() -> Credentials {
+    let partner_login = PartnerLogin {
+        partner_id: "42".to_owned(),
+        partner_auth_token: "partner-token".to_owned(),
+        sync_time: SYNC_TIME.to_owned(),
+    };
+    let mut credentials =
+        Credentials::with_partner_login("me", "hunter2", &Partner::android(), partner_login)
+            .unwrap();
+    credentials.set_user_login(UserLogin {
+        user_id: Some("123".to_owned()),
+        user_auth_token: "user-token".to_owned(),
+    });
+    credentials
+}
+
+/// Transport answering each call with the next response of a script and
+/// keeping the methods called.
+#[derive(Debug, Default)]
+pub struct Script {
+    responses: Mutex<VecDeque<Value>>,
+    calls: Mutex<Vec<Method>>,
+}
+
+impl Script {
+    pub fn new(responses: Vec<Value>) -> Arc<Self> {
+        Arc::new(Script {
+            responses: Mutex::new(responses.into()),
+            calls: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Returns a logged in `Pandora` sending its calls to the script.
+    pub fn pandora(self: &Arc<Self>) -> Pandora {
+        Pandora::with_credentials(credentials()).transport(Arc::clone(self))
+    }
+
+    /// Returns the methods called so far.
+    pub fn calls(&self) -> Vec<Method> {
+        self.calls.lock().unwrap().clone()
+    }
+}
+
+impl Transport for Script {
+    fn execute(&self, request: &HttpRequest) -> Result<HttpResponse> {
+        self.calls.lock().unwrap().push(request.method.clone());
+        let body = self.responses.lock().unwrap().pop_front();
+        let body = body.unwrap_or_else(|| panic!("unexpected call {}", request.method));
+        Ok(HttpResponse {
+            status: 200,
+            body: body.to_string(),
+        })
+    }
+}
+
+/// A `station.getPlaylist` response with a track per token, or an ad for
+/// the tokens starting with `ad:`.
+pub fn tracks(tokens: &[&str]) -> Value {
+    let items: Vec<Value> = tokens
+        .iter()
+        .map(|token| match token.strip_prefix("ad:") {
+            Some(ad) => json!({ "adToken": ad }),
+            None => json!({ "trackToken": token }),
+        })
+        .collect();
+    json!({ "stat": "ok", "result": { "items": items } })
+}
+
+/// An `auth.partnerLogin` response for partner `42`.
+pub fn partner_login() -> Value {
+    json!({
+        "stat": "ok",
+        "result": {
+            "partnerId": "42",
+            "partnerAuthToken": "partner-token",
+            "syncTime": SYNC_TIME,
+        },
+    })
+}
+
+/// A response without a result.
+pub fn ok() -> Value {
+    json!({ "stat": "ok" })
+}
+
+/// A failed call with the given API error code.
+pub fn fail(code: u32) -> Value {
+    json!({ "stat": "fail", "message": "failed", "code": code })
+}
+
+/// Serves HTTP on a local port and returns its URL. Each connection gets
+/// what `respond` returns for the request head, and is then closed.
+pub fn serve<F>(respond: F) -> String
+where
+    F: Fn(&str) -> Vec<u8> + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = match stream {
+                Ok(stream) => stream,
+                Err(_) => return,
+            };
+            let head = read_head(&mut stream);
+            let _ = stream.write_all(&respond(&head));
+        }
+    });
+    url
+}
+
+/// Returns a response with the given status line, such as `"200 OK"`,
+/// extra headers and body.
+pub fn response(status: &str, headers: &[(&str, &str)], body: &[u8]) -> Vec<u8> {
+    let mut response = format!("HTTP/1.1 {}\r\nConnection: close\r\n", status);
+    for (name, value) in headers {
+        response.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    response.push_str("\r\n");
+
+    let mut response = response.into_bytes();
+    response.extend_from_slice(body);
+    response
+}
+
+/// Returns the value of a request header, matched case-insensitively.
+pub fn header<'a>(head: &'a str, name: &str) -> Option<&'a str> {
+    head.lines().skip(1).find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        if key.eq_ignore_ascii_case(name) {
+            Some(value.trim())
+        } else {
+            None
+        }
+    })
+}
+
+fn read_head(stream: &mut TcpStream) -> String {
+    let mut head = Vec::new();
+    let mut byte = [0];
+    while !head.ends_with(b"\r\n\r\n") {
+        match stream.read(&mut byte) {
+            Ok(1) => head.push(byte[0]),
+            _ => break,
+        }
+    }
+    String::from_utf8_lossy(&head).into_owned()
+}
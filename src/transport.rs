@@ -0,0 +1,43 @@
+//! How encoded calls reach the API.
+//!
+//! A `Pandora` sends its calls over HTTP with a `reqwest::Client`. Other
+//! transports can stand in for it, like the recording and replaying ones
+//! in `cassette`.
+
+use reqwest::Client;
+
+use super::error::Result;
+use super::protocol::{HttpRequest, HttpResponse};
+use super::request::execute;
+
+use std::fmt;
+use std::sync::Arc;
+
+/// Sends an encoded call and returns the response.
+pub trait Transport: fmt::Debug + Send + Sync {
+    fn execute(&self, request: &HttpRequest) -> Result<HttpResponse>;
+}
+
+impl Transport for Client {
+    fn execute(&self, request: &HttpRequest) -> Result<HttpResponse> {
+        execute(self, request)
+    }
+}
+
+impl<X> Transport for Arc<X>
+where
+    X: Transport + ?Sized,
+{
+    fn execute(&self, request: &HttpRequest) -> Result<HttpResponse> {
+        (**self).execute(request)
+    }
+}
+
+impl<X> Transport for Box<X>
+where
+    X: Transport + ?Sized,
+{
+    fn execute(&self, request: &HttpRequest) -> Result<HttpResponse> {
+        (**self).execute(request)
+    }
+}
@@ -0,0 +1,280 @@
+//! Golden tests for the URL and body sent for every `Method`.
+//!
+//! Each method is called once through the crate's public API, so the
+//! goldens cover the real request structs. Each has a file in
+//! `tests/golden` with the URL, the JSON body after `build_body`, and the
+//! body as sent. Run with `UPDATE_GOLDEN=1` to rewrite them after an
+//! intended protocol change.
+
+extern crate pandora_rs2;
+
+use pandora_rs2::auth::{Partner, PartnerLogin, UserLogin, UserLoginRequest};
+use pandora_rs2::crypt;
+use pandora_rs2::error::Result;
+use pandora_rs2::method::Method;
+use pandora_rs2::music::Music;
+use pandora_rs2::playlist::Playlist;
+use pandora_rs2::protocol::{encode_call, HttpRequest, HttpResponse};
+use pandora_rs2::stations::Seed;
+use pandora_rs2::{Credentials, Pandora, Transport, DEFAULT_ENDPOINT};
+
+use serde_json::{json, Value};
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+const PARTNER_AUTH_TOKEN: &str = "VAzrFQTtsy3BQ3K+3BBvOQ==";
+const USER_AUTH_TOKEN: &str = "XXXvM4Rb4Ti2GK4aXTkEdpdZd7Q5j5wbHn";
+const STATION_TOKEN: &str = "3914377363925266";
+const TRACK_TOKEN: &str = "d6aa37d2e4b1d9bbd8b7d9a7e1c4a6b3";
+const MUSIC_TOKEN: &str = "R526";
+const BOOKMARK_TOKEN: &str = "3185294128869236537";
+
+/// Every method, built so that adding a variant fails to compile here
+/// until it's listed.
+fn all_methods() -> Vec<Method> {
+    let methods = vec![
+        Method::TestCheckLicensing,
+        Method::AuthPartnerLogin,
+        Method::AuthUserLogin,
+        Method::MusicSearch,
+        Method::UserGetStationList,
+        Method::UserGetStationListChecksum,
+        Method::UserSleepSong,
+        Method::UserGetBookmarks,
+        Method::BookmarkAddSongBookmark,
+        Method::BookmarkAddArtistBookmark,
+        Method::BookmarkDeleteSongBookmark,
+        Method::BookmarkDeleteArtistBookmark,
+        Method::StationCreateStation,
+        Method::StationRenameStation,
+        Method::StationDeleteStation,
+        Method::StationGetStation,
+        Method::StationAddMusic,
+        Method::StationDeleteMusic,
+        Method::StationGetPlaylist,
+        Method::StationAddFeedback,
+        Method::custom("test.custom"),
+    ];
+    for method in &methods {
+        match *method {
+            Method::TestCheckLicensing
+            | Method::AuthPartnerLogin
+            | Method::AuthUserLogin
+            | Method::MusicSearch
+            | Method::UserGetStationList
+            | Method::UserGetStationListChecksum
+            | Method::UserSleepSong
+            | Method::UserGetBookmarks
+            | Method::BookmarkAddSongBookmark
+            | Method::BookmarkAddArtistBookmark
+            | Method::BookmarkDeleteSongBookmark
+            | Method::BookmarkDeleteArtistBookmark
+            | Method::StationCreateStation
+            | Method::StationRenameStation
+            | Method::StationDeleteStation
+            | Method::StationGetStation
+            | Method::StationAddMusic
+            | Method::StationDeleteMusic
+            | Method::StationGetPlaylist
+            | Method::StationAddFeedback
+            | Method::Custom { .. } => {}
+        }
+    }
+    methods
+}
+
+/// Credentials after `auth.partnerLogin`, with a sync time of 1477631903.
+fn partner_credentials() -> Credentials {
+    let partner = Partner::android();
+    let partner_login = PartnerLogin {
+        partner_id: "42".to_owned(),
+        partner_auth_token: PARTNER_AUTH_TOKEN.to_owned(),
+        sync_time: "87f9b461070d0efd5a143d430aafebf9".to_owned(),
+    };
+    Credentials::with_partner_login("user@example.com", "hunter2", &partner, partner_login).unwrap()
+}
+
+/// Credentials of a logged in user.
+fn credentials() -> Credentials {
+    let mut credentials = partner_credentials();
+    credentials.set_user_login(UserLogin {
+        user_id: Some("123456789".to_owned()),
+        user_auth_token: USER_AUTH_TOKEN.to_owned(),
+    });
+    credentials
+}
+
+/// Transport keeping every request, answering each with an empty success.
+#[derive(Debug, Default)]
+struct Capture(Mutex<Vec<HttpRequest>>);
+
+impl Transport for Capture {
+    fn execute(&self, request: &HttpRequest) -> Result<HttpResponse> {
+        self.0.lock().unwrap().push(request.clone());
+        Ok(HttpResponse {
+            status: 200,
+            body: r#"{"stat":"ok"}"#.to_owned(),
+        })
+    }
+}
+
+/// Returns the request of one call of every method, in the order of
+/// `all_methods`. The logins are encoded with the credentials available
+/// at that point; the other calls go through a logged in `Pandora`.
+fn requests() -> Vec<HttpRequest> {
+    let mut requests = Vec::new();
+    let capture = Arc::new(Capture::default());
+    let pandora = Pandora::with_credentials(credentials()).transport(Arc::clone(&capture));
+    let stations = pandora.stations();
+    let bookmarks = pandora.bookmarks();
+    let playlist = Playlist::new(&pandora, &STATION_TOKEN.to_owned());
+    let station = STATION_TOKEN.to_owned();
+    let track = TRACK_TOKEN.to_owned();
+    let music = MUSIC_TOKEN.to_owned();
+    let seed: Seed = serde_json::from_value(json!({ "seedId": "R8943289" })).unwrap();
+
+    // Results are ignored: the empty responses don't decode into most of
+    // them, and only the requests matter here.
+    let _ = pandora.request::<Value>(Method::TestCheckLicensing, None);
+    let take = |requests: &mut Vec<HttpRequest>| {
+        requests.append(&mut capture.0.lock().unwrap());
+    };
+    take(&mut requests);
+
+    requests.push(encode_call(DEFAULT_ENDPOINT, &Partner::android(), None).unwrap());
+    let user_login = UserLoginRequest::new("user@example.com".to_owned(), "hunter2".to_owned());
+    requests
+        .push(encode_call(DEFAULT_ENDPOINT, &user_login, Some(&partner_credentials())).unwrap());
+
+    let _ = Music::new(&pandora).search("Chopin");
+    let _ = stations.list();
+    let _ = stations.checksum();
+    let _ = playlist.sleep(track.clone());
+    let _ = bookmarks.list();
+    let _ = bookmarks.add_song(track.clone());
+    let _ = bookmarks.add_artist(track.clone());
+    let _ = bookmarks.delete_song(BOOKMARK_TOKEN);
+    let _ = bookmarks.delete_artist(BOOKMARK_TOKEN);
+    let _ = stations.create(&music);
+    let _ = stations.rename(&station, "Chopin Radio");
+    let _ = stations.delete(&station);
+    let _ = stations.station(&station);
+    let _ = stations.add_seed(&station, &music);
+    let _ = stations.remove_seed(&seed);
+    let _ = playlist.list();
+    let _ = playlist.rate(track, true);
+    let _ = pandora.request::<Value>(
+        Method::custom("test.custom"),
+        Some(json!({ "example": "value" })),
+    );
+    take(&mut requests);
+
+    let methods: Vec<Method> = requests.iter().map(|r| r.method.clone()).collect();
+    assert_eq!(methods, all_methods());
+    requests
+}
+
+fn render(request: &HttpRequest) -> String {
+    format!(
+        "url: {}\nplain: {}\nbody: {}\n",
+        request.url, request.plain_body, request.body
+    )
+}
+
+fn golden_path(method: &Method) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(format!("{}.txt", method))
+}
+
+#[test]
+fn requests_match_golden_files() {
+    let update = env::var_os("UPDATE_GOLDEN").is_some();
+    let mut mismatches = Vec::new();
+
+    for request in requests() {
+        let method = &request.method;
+        let actual = render(&request);
+        let path = golden_path(method);
+        if update {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, &actual).unwrap();
+            continue;
+        }
+
+        let expected = fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("{}: {} (run with UPDATE_GOLDEN=1)", path.display(), e));
+        if expected != actual {
+            mismatches.push(format!(
+                "{}:\n--- expected\n{}--- actual\n{}",
+                method, expected, actual
+            ));
+        }
+    }
+
+    assert!(mismatches.is_empty(), "{}", mismatches.join("\n"));
+}
+
+#[test]
+fn auth_parameters_are_injected() {
+    for request in requests() {
+        let method = &request.method;
+        let query: Vec<(String, String)> = request.url.query_pairs().into_owned().collect();
+        let pair = |key: &str, value: &str| (key.to_owned(), value.to_owned());
+        let mut expected_query = vec![pair("method", &method.to_string())];
+        let mut expected_body = json!({});
+
+        match *method {
+            Method::AuthPartnerLogin => {}
+            Method::AuthUserLogin => {
+                expected_query.push(pair("auth_token", PARTNER_AUTH_TOKEN));
+                expected_query.push(pair("partner_id", "42"));
+                expected_body["partnerAuthToken"] = json!(PARTNER_AUTH_TOKEN);
+                expected_body["syncTime"] = json!(1477631903);
+            }
+            _ => {
+                expected_query.push(pair("auth_token", USER_AUTH_TOKEN));
+                expected_query.push(pair("partner_id", "42"));
+                expected_query.push(pair("user_id", "123456789"));
+                expected_body["partnerAuthToken"] = json!(PARTNER_AUTH_TOKEN);
+                expected_body["syncTime"] = json!(1477631903);
+                expected_body["userAuthToken"] = json!(USER_AUTH_TOKEN);
+            }
+        }
+        assert_eq!(query, expected_query, "{}", method);
+
+        let body: Value = serde_json::from_str(&request.plain_body).unwrap();
+        for key in &["partnerAuthToken", "syncTime", "userAuthToken"] {
+            assert_eq!(
+                body.get(*key),
+                expected_body.get(*key),
+                "{} {}",
+                method,
+                key
+            );
+        }
+    }
+}
+
+#[test]
+fn only_login_and_licensing_are_plain_text() {
+    let key = Partner::android().encrypt_password().to_owned();
+    for request in requests() {
+        let method = &request.method;
+        match *method {
+            Method::TestCheckLicensing | Method::AuthPartnerLogin => {
+                assert_eq!(request.body, request.plain_body, "{}", method)
+            }
+            _ => assert_eq!(
+                request.body,
+                crypt::encrypt(&key, &request.plain_body),
+                "{}",
+                method
+            ),
+        }
+    }
+}
@@ -0,0 +1,183 @@
+//! Contract tests decoding recorded API responses from `tests/fixtures`
+//! into the crate's types.
+
+extern crate pandora_rs2;
+#[macro_use]
+extern crate serde_derive;
+
+use pandora_rs2::bookmarks::BookmarkList;
+use pandora_rs2::error::Error;
+use pandora_rs2::method::Method;
+use pandora_rs2::music::SearchResults;
+use pandora_rs2::playlist::{Quality, Track};
+use pandora_rs2::protocol::{decode, encode, HttpResponse};
+use pandora_rs2::stations::{ExtendedStation, Seed, Station, StationList, StationListChecksum};
+use pandora_rs2::DEFAULT_ENDPOINT;
+
+use serde::de::{DeserializeOwned, IgnoredAny};
+use serde_json::Value;
+
+use std::fs;
+use std::path::PathBuf;
+
+/// Result of `station.getPlaylist`.
+#[derive(Deserialize)]
+struct Tracklist {
+    items: Vec<Track>,
+}
+
+fn fixture(name: &str) -> String {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join(name);
+    fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e))
+}
+
+/// Decodes a response body as the result of a call to `method`.
+fn decode_body<T>(method: Method, body: &str) -> Result<T, Error>
+where
+    T: DeserializeOwned,
+{
+    let request = encode(DEFAULT_ENDPOINT, method, false, None, None).unwrap();
+    let response = HttpResponse {
+        status: 200,
+        body: body.to_owned(),
+    };
+    decode(&request, &response)
+}
+
+fn decode_fixture<T>(method: Method, name: &str) -> T
+where
+    T: DeserializeOwned,
+{
+    decode_body(method, &fixture(name)).unwrap_or_else(|e| panic!("{}: {}", name, e))
+}
+
+#[test]
+fn station_list() {
+    let list: StationList = decode_fixture(Method::UserGetStationList, "station_list.json");
+    assert_eq!(list.checksum, "99a1ae4e1d0b8ef2c1fd3eba46fe2cfe");
+    let names: Vec<&str> = list
+        .stations
+        .iter()
+        .map(|station| station.station_name.as_str())
+        .collect();
+    assert_eq!(names, ["QuickMix", "Chopin Radio"]);
+
+    let checksum: StationListChecksum = decode_fixture(
+        Method::UserGetStationListChecksum,
+        "station_list_checksum.json",
+    );
+    assert_eq!(checksum.checksum, list.checksum);
+}
+
+#[test]
+fn station() {
+    let station: Station = decode_fixture(Method::StationGetStation, "station.json");
+    assert_eq!(station.station_id, "3914377363925266");
+    assert_eq!(station.station_name, "Chopin Radio");
+
+    let seed: Seed = decode_fixture(Method::StationAddMusic, "seed.json");
+    assert_eq!(seed.seed_id, "R8943289");
+}
+
+#[test]
+fn extended_station_without_genres() {
+    let station: ExtendedStation =
+        decode_fixture(Method::StationGetStation, "station_extended.json");
+    let music = station.music.expect("station has seeds");
+    assert_eq!(
+        music.songs[0].song_name,
+        "Nocturne No. 2 In E Flat, Op. 9 No. 2"
+    );
+    assert_eq!(music.artists[0].music_token, "R526");
+    assert!(music.genre.is_none());
+}
+
+#[test]
+fn playlist_with_ads() {
+    let tracklist: Tracklist = decode_fixture(Method::StationGetPlaylist, "playlist.json");
+    let ads: Vec<bool> = tracklist.items.iter().map(Track::is_ad).collect();
+    assert_eq!(ads, [false, true, false]);
+
+    let track = &tracklist.items[0];
+    assert_eq!(
+        track.song_name.as_deref(),
+        Some("Nocturne No. 2 In E Flat, Op. 9 No. 2")
+    );
+    assert_eq!(track.song_rating, Some(0));
+    assert!(track.additional_audio_url.is_some());
+    let audio = track.track_audio.as_ref().expect("track has audio");
+    assert_eq!(audio.len(), 3);
+    assert_eq!(audio.get(&Quality::High).unwrap().bitrate, "64");
+    assert_eq!(audio.get(&Quality::Low).unwrap().bitrate, "32");
+
+    let ad = &tracklist.items[1];
+    assert!(ad.track_token.is_none());
+    assert!(ad.track_audio.is_none());
+}
+
+#[test]
+fn playlist_with_partial_audio_map() {
+    let tracklist: Tracklist =
+        decode_fixture(Method::StationGetPlaylist, "playlist_partial_audio.json");
+    let audio = tracklist.items[0].track_audio.as_ref().unwrap();
+    assert!(audio.get(&Quality::Low).is_none());
+    assert!(audio.get(&Quality::Medium).is_none());
+
+    let hifi = Quality::Other("hifiQuality".to_owned());
+    assert_eq!(audio.get(&hifi).unwrap().encoding, "aac");
+    assert_eq!(audio.best().unwrap().bitrate, "192");
+    assert_eq!(audio.at_most(128).unwrap().bitrate, "128");
+    assert!(audio.at_most(64).is_none());
+}
+
+#[test]
+fn search() {
+    let results: SearchResults = decode_fixture(Method::MusicSearch, "search.json");
+    assert!(results.near_matches_available());
+    assert_eq!(results.songs()[0].song_name, "Strategic Grill Locations");
+    assert_eq!(results.artists().len(), 2);
+    assert_eq!(results.artists()[1].music_token, "C144117");
+}
+
+#[test]
+fn bookmarks() {
+    let bookmarks: BookmarkList = decode_fixture(Method::UserGetBookmarks, "bookmarks.json");
+    assert_eq!(bookmarks.songs[0].song_name, "Take Five");
+    assert_eq!(bookmarks.songs[0].album_name.as_deref(), Some("Time Out"));
+    assert_eq!(bookmarks.artists[0].bookmark_token, "3185294128869236537");
+}
+
+#[test]
+fn calls_without_a_result() {
+    for name in &["empty_result.json", "no_result.json"] {
+        let _: IgnoredAny = decode_fixture(Method::StationDeleteStation, name);
+    }
+    match decode_body::<Station>(Method::StationGetStation, &fixture("no_result.json")) {
+        Err(Error::MissingResult { .. }) => {}
+        res => panic!("expected MissingResult, got {:?}", res),
+    }
+}
+
+#[test]
+fn errors() {
+    #[derive(Deserialize)]
+    struct Case {
+        name: String,
+        response: Value,
+    }
+
+    let cases: Vec<Case> = serde_json::from_str(&fixture("errors.json")).unwrap();
+    for case in cases {
+        let code = case.response["code"].as_u64().map(|code| code as u32);
+        match decode_body::<Value>(Method::UserGetStationList, &case.response.to_string()) {
+            Err(Error::Api { code: api_code, .. }) => {
+                assert_eq!(api_code.name(), case.name, "code {:?}", code);
+                assert_eq!(api_code.code(), code, "{}", case.name);
+            }
+            res => panic!("code {:?}: expected an API error, got {:?}", code, res),
+        }
+    }
+}